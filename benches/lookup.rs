@@ -6,7 +6,10 @@ use criterion::{
 use rand::prelude::*;
 use rand::rngs::SmallRng;
 
-use psy::prefix_sum_index;
+use psy::{
+    prefix_sum_index, prefix_sum_index_batch, prefix_sum_index_u16, prefix_sum_index_with,
+    prefix_sums_into, Backend, Layout, PrefixSumIndex,
+};
 
 pub fn bench_lookup(c: &mut Criterion) {
     let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
@@ -15,7 +18,7 @@ pub fn bench_lookup(c: &mut Criterion) {
 
     let mut rng = SmallRng::seed_from_u64(0);
 
-    for size in [16 /*, 50, 64, 100, 128, 1_024*/] {
+    for size in [16, 64, 100, 256, 1_024, 16_384, 131_072, 1_048_576] {
         let mut prefixes = Vec::with_capacity(size);
         let mut sum = 0;
         let sums = (0..size)
@@ -45,6 +48,154 @@ pub fn bench_lookup(c: &mut Criterion) {
                 })
             },
         );
+        group.bench_with_input(
+            BenchmarkId::new("batch lookup", size),
+            &prefixes,
+            |b, prefixes| {
+                let mut out = vec![Err(0); lookups.len()];
+                b.iter(|| {
+                    prefix_sum_index_batch(prefixes, &lookups, &mut out);
+                    black_box(&out);
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("block index", size),
+            &PrefixSumIndex::new(prefixes.clone()),
+            |b, index| {
+                b.iter(|| {
+                    for lookup in &lookups {
+                        let _ = black_box(index.prefix_sum_index(*lookup));
+                    }
+                })
+            },
+        );
+        for backend in Backend::available() {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} lookup", backend), size),
+                &prefixes,
+                |b, prefixes| {
+                    b.iter(|| {
+                        for lookup in &lookups {
+                            let _ = black_box(prefix_sum_index_with(backend, prefixes, *lookup));
+                        }
+                    })
+                },
+            );
+        }
+        group.bench_with_input(
+            BenchmarkId::new("slice::binary_search", size),
+            &sums,
+            |b, sums| {
+                b.iter(|| {
+                    for lookup in &lookups {
+                        let _ = black_box(lookup_in_slice(sums, *lookup));
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+pub fn bench_lookup_u16(c: &mut Criterion) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+    let mut group = c.benchmark_group("lookup_u16");
+    group.plot_config(plot_config);
+
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    for size in [16, 64, 256, 1_024, 16_384] {
+        let mut prefixes = Vec::with_capacity(size);
+        let mut sum = 0;
+        let sums = (0..size)
+            .map(|_| {
+                let prefix: u16 = rng.gen();
+                prefixes.push(prefix);
+                sum += prefix as usize;
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let lookup_range = 0..sum;
+        let lookups: Vec<_> = (0..1000)
+            .map(|_| rng.gen_range(lookup_range.clone()))
+            .collect();
+
+        group.throughput(Throughput::Elements(size as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("naive lookup", size),
+            &prefixes,
+            |b, prefixes| {
+                b.iter(|| {
+                    for lookup in &lookups {
+                        let _ = black_box(prefix_sum_index_u16(prefixes, *lookup));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("slice::binary_search", size),
+            &sums,
+            |b, sums| {
+                b.iter(|| {
+                    for lookup in &lookups {
+                        let _ = black_box(lookup_in_slice(sums, *lookup));
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Compares the [`PrefixSumIndex`] layouts on large tables.
+///
+/// By default this only goes up to 10M entries. Set `PSY_BENCH_LARGE` to also
+/// run 100M and 1B entries, the largest of which needs about 10 GB of memory
+/// for the materialized sums that `slice::binary_search` works on.
+pub fn bench_lookup_large(c: &mut Criterion) {
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+    let mut group = c.benchmark_group("lookup_large");
+    group.plot_config(plot_config);
+    group.sample_size(10);
+
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    let mut sizes = vec![1_000_000, 10_000_000];
+    if std::env::var_os("PSY_BENCH_LARGE").is_some() {
+        sizes.extend([100_000_000, 1_000_000_000]);
+    }
+
+    for size in sizes {
+        let mut prefixes = vec![0u8; size];
+        rng.fill(&mut prefixes[..]);
+        let mut sums = vec![0u64; size];
+        prefix_sums_into(&prefixes, &mut sums);
+
+        let lookups: Vec<u64> = (0..1000)
+            .map(|_| rng.gen_range(0..sums[size - 1]))
+            .collect();
+
+        group.throughput(Throughput::Elements(lookups.len() as u64));
+
+        let layouts = [
+            ("flat index", Layout::default()),
+            ("two-level index", Layout::TWO_LEVEL),
+        ];
+        for (name, layout) in layouts {
+            let index = PrefixSumIndex::with_layout(prefixes.clone(), layout);
+            group.bench_with_input(BenchmarkId::new(name, size), &index, |b, index| {
+                b.iter(|| {
+                    for lookup in &lookups {
+                        let _ = black_box(index.prefix_sum_index(*lookup as usize));
+                    }
+                })
+            });
+        }
         group.bench_with_input(
             BenchmarkId::new("slice::binary_search", size),
             &sums,
@@ -61,10 +212,10 @@ pub fn bench_lookup(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_lookup);
+criterion_group!(benches, bench_lookup, bench_lookup_u16, bench_lookup_large);
 criterion_main!(benches);
 
-fn lookup_in_slice(s: &[usize], lookup: usize) -> Result<(usize, usize), usize> {
+fn lookup_in_slice<T: Copy + Default + Ord>(s: &[T], lookup: T) -> Result<(usize, T), T> {
     let idx = match s.binary_search_by_key(&lookup, |sum| *sum) {
         Ok(idx) => idx + 1,
         Err(idx) => idx,
@@ -1,8 +1,28 @@
+use core::arch::x86_64::*;
+
+use crate::sse2::{load_remainder, sum_first_bytes};
+
 /// Calculate the Prefix Sum Index using AVX intrinsics.
 ///
 /// See [`crate level docs`](crate) for more information.
 #[target_feature(enable = "avx")]
 pub unsafe fn prefix_sum_8(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    prefix_sum_8_impl(offsets, lookup)
+}
+
+/// Calculate the Prefix Sum Index using SSE4.1 intrinsics.
+///
+/// This is the same code as [`prefix_sum_8`], the widening of the `u8`s
+/// only needs SSE4.1, the AVX version just benefits from the VEX encoding.
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn prefix_sum_8_sse41(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    prefix_sum_8_impl(offsets, lookup)
+}
+
+// The implementation is `inline(always)`, so it is compiled with the target
+// features of the function it is inlined into.
+#[inline(always)]
+unsafe fn prefix_sum_8_impl(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
     let mut start = 0;
     let mut index = 0;
 
@@ -19,31 +39,33 @@ pub unsafe fn prefix_sum_8(offsets: &[u8], lookup: usize) -> Result<(usize, usiz
         index += 8;
     }
     let remainder = chunks.remainder();
-    let mut buf = [0u8; 8];
-    {
-        let (prefix, _) = buf.split_at_mut(remainder.len());
-        prefix.copy_from_slice(remainder);
+    if remainder.is_empty() {
+        return Err(start);
     }
-    match prefix_sum_8_inner(&buf, lookup - start) {
-        Ok((idx, sum)) => Ok((index + idx, start + sum)),
+    // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 8.
+    let (mm, skip) = load_remainder::<8>(offsets, remainder.len());
+    match prefix_sum_8_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip, start + sum)),
         Err(sum) => Err(start + sum),
     }
 }
 
-#[target_feature(enable = "avx")]
+#[inline(always)]
 unsafe fn prefix_sum_8_inner(offsets: &[u8; 8], lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY: we do an unaligned load of exactly 8 bytes from an 8-byte array.
+    let mm = _mm_loadl_epi64(offsets.as_ptr() as *const __m128i);
+    prefix_sum_8_simd(mm, lookup)
+}
+
+/// Works on the 8 offsets in the low 8 bytes of `offsets`.
+#[inline(always)]
+unsafe fn prefix_sum_8_simd(offsets: __m128i, lookup: usize) -> Result<(usize, usize), usize> {
     // SAFETY:
-    // - we have a 16-byte stack allocation that we don’t index out of bounds.
     // - the prefix sum itself is bounded to `u8::MAX * 8`, which is `< i16::MAX`.
     // - we check that lookup is `< i16::MAX` to avoid overflow.
-    use core::arch::x86_64::*;
-    // copy the 8 bytes into the first 8 of 16 bytes
-    let mut mm_buf: __m128i = core::mem::zeroed();
-    *(&mut mm_buf as *mut __m128i as *mut [u8; 8]) = *offsets;
-    // load the 16 bytes into a m128
-    let mut mm = _mm_load_si128(&mm_buf as *const __m128i);
+
     // spread the 8xu8 in the first 64bit out to 8xi16
-    mm = _mm_cvtepu8_epi16(mm);
+    let mut mm = _mm_cvtepu8_epi16(offsets);
 
     // do the prefix sum, simplified like this, except we have 8 values:
     //   [a,     b,         c,             d]
@@ -55,11 +77,9 @@ unsafe fn prefix_sum_8_inner(offsets: &[u8; 8], lookup: usize) -> Result<(usize,
     mm = _mm_add_epi16(mm, _mm_slli_si128::<4>(mm));
     mm = _mm_add_epi16(mm, _mm_slli_si128::<2>(mm));
 
-    _mm_store_si128(&mut mm_buf, mm);
-    let u16_buf = &*(&mm_buf as *const __m128i as *const [u16; 8]);
-
+    let total = _mm_extract_epi16::<7>(mm) as u16 as usize;
     if lookup > i16::MAX as usize {
-        return Err(u16_buf[7] as usize);
+        return Err(total);
     }
 
     // compare each i16 with our lookup
@@ -72,9 +92,9 @@ unsafe fn prefix_sum_8_inner(offsets: &[u8; 8], lookup: usize) -> Result<(usize,
     // trailing, because we are dealing with little-endian bytes here
     let idx = mask.trailing_zeros() as usize / 2;
     if idx > 7 {
-        Err(u16_buf[7] as usize)
+        Err(total)
     } else {
-        Ok((idx, u16_buf[idx] as usize))
+        Ok((idx, sum_first_bytes(offsets, idx + 1)))
     }
 }
 
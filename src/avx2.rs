@@ -1,11 +1,34 @@
+use core::arch::x86_64::*;
+
+use crate::scan::ScanOutput;
+use crate::sse2::{as_bytes, load_remainder, sum_first_bytes, sum_inline};
+
 /// Calculate the Prefix Sum Index using AVX2 intrinsics.
 ///
 /// See [`crate level docs`](crate) for more information.
 #[target_feature(enable = "avx2")]
 pub unsafe fn prefix_sum_16(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    let all = offsets;
     let mut start = 0;
     let mut index = 0;
 
+    // For long slices, most blocks do not contain the lookup. We can skip those
+    // by just summing them up, which is a lot cheaper than the full prefix sum.
+    let mut blocks = offsets.chunks_exact(64);
+    for block in &mut blocks {
+        // SAFETY: `chunks_exact` guarantees this is a `&[u8; 64]`
+        let block = &*(block as *const [u8] as *const [u8; 64]);
+        let sum = sum_64(block);
+        if start + sum > lookup {
+            break;
+        }
+        start += sum;
+        index += 64;
+    }
+    // The remaining slice has the lookup within its first 64 bytes, or is
+    // shorter than a block.
+    let offsets = &offsets[index..];
+
     //let mut chunks = offsets.array_chunks();
     let mut chunks = offsets.chunks_exact(16);
     for chunk in &mut chunks {
@@ -16,35 +39,67 @@ pub unsafe fn prefix_sum_16(offsets: &[u8], lookup: usize) -> Result<(usize, usi
             Ok((idx, sum)) => return Ok((index + idx, start + sum)),
             Err(sum) => start += sum,
         }
-        index += 8;
+        index += 16;
     }
     let remainder = chunks.remainder();
-    let mut buf: [u8; 16] = core::mem::zeroed();
-    {
-        let (prefix, _) = buf.split_at_mut(remainder.len());
-        prefix.copy_from_slice(remainder);
+    if remainder.is_empty() {
+        return Err(start);
     }
-    match prefix_sum_16_inner(&buf, lookup - start) {
-        Ok((idx, sum)) => Ok((index + idx, start + sum)),
+    // SAFETY: `remainder` is the non-empty end of `all`, and shorter than 16.
+    let (mm, skip) = load_remainder::<16>(all, remainder.len());
+    match prefix_sum_16_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip, start + sum)),
         Err(sum) => Err(start + sum),
     }
 }
 
+/// Sums up 64 `u8`s.
 #[target_feature(enable = "avx2")]
-unsafe fn prefix_sum_16_inner(offsets: &[u8; 16], lookup: usize) -> Result<(usize, usize), usize> {
+unsafe fn sum_64(offsets: &[u8; 64]) -> usize {
     // SAFETY:
-    // - we have a 32-byte stack allocation that we don’t index out of bounds.
-    // - the prefix sum itself is bounded to `u8::MAX * 16`, which is `< i16::MAX`.
-    // - we check that lookup is `< i16::MAX` to avoid overflow.
-    use core::arch::x86_64::*;
-    // copy the 16 bytes
-    let mut mm_buf: __m256i = core::mem::zeroed();
-    *(&mut mm_buf as *mut __m256i as *mut [u8; 16]) = *offsets;
-    // load the 16 bytes into a m128
-    let mm = _mm_load_si128(&mm_buf as *const __m256i as *const __m128i);
-    // spread the 16xu8 in out to 16xi16
-    let mut mm = _mm256_cvtepu8_epi16(mm);
+    // - we do two unaligned loads of 32 bytes each from a 64-byte array.
+    let ptr = offsets.as_ptr() as *const __m256i;
+    let a = _mm256_loadu_si256(ptr);
+    let b = _mm256_loadu_si256(ptr.add(1));
+
+    // `sad` sums up the absolute difference to zero of each 8 bytes into a u64
+    let zero = _mm256_setzero_si256();
+    let sums = _mm256_add_epi64(_mm256_sad_epu8(a, zero), _mm256_sad_epu8(b, zero));
+
+    // and then we add up the 4 u64s horizontally
+    let sums = _mm_add_epi64(
+        _mm256_castsi256_si128(sums),
+        _mm256_extracti128_si256::<1>(sums),
+    );
+    let sums = _mm_add_epi64(sums, _mm_unpackhi_epi64(sums, sums));
+    _mm_cvtsi128_si64(sums) as usize
+}
+
+/// Sums up all the `offsets` using AVX2 intrinsics.
+#[target_feature(enable = "avx2")]
+pub unsafe fn sum(offsets: &[u8]) -> usize {
+    let zero = _mm256_setzero_si256();
+    // the `sad` results are u64s, which can not overflow
+    let mut sums = zero;
 
+    let mut chunks = offsets.chunks_exact(32);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees we have 32 bytes to load.
+        let mm = _mm256_loadu_si256(chunk.as_ptr() as *const __m256i);
+        sums = _mm256_add_epi64(sums, _mm256_sad_epu8(mm, zero));
+    }
+
+    let sums = _mm_add_epi64(
+        _mm256_castsi256_si128(sums),
+        _mm256_extracti128_si256::<1>(sums),
+    );
+    let sums = _mm_add_epi64(sums, _mm_unpackhi_epi64(sums, sums));
+    _mm_cvtsi128_si64(sums) as usize + sum_inline(chunks.remainder())
+}
+
+/// Calculates the prefix sum of the 16xi16.
+#[inline(always)]
+unsafe fn scan_16(mut mm: __m256i) -> __m256i {
     // do the prefix sum, simplified like this, except we have 8 values:
     //   [a,     b,         c,             d]
     // + [0,     0, a        ,     b        ]
@@ -56,17 +111,93 @@ unsafe fn prefix_sum_16_inner(offsets: &[u8; 16], lookup: usize) -> Result<(usiz
     mm = _mm256_add_epi16(mm, _mm256_slli_si256::<8>(mm));
 
     // we can’t shift by 16 bytes, as 256-bit simd works with 128-bit lanes :-(
-    // but this is a prefix sum after all, so we can just add the last
-    // 16-bit element of the first 128-lane to all the 16-bit elements of the second
-    let hi = _mm_set1_epi16(_mm256_extract_epi16::<7>(mm) as i16);
-    let shifted = _mm256_set_m128i(hi, _mm_setzero_si128());
-    mm = _mm256_add_epi16(mm, shifted);
+    // but this is a prefix sum after all, so we can just broadcast the last
+    // 16-bit element of the first 128-lane, and add it to all the 16-bit
+    // elements of the second
+    let carry = _mm_shufflehi_epi16::<0b11_11_11_11>(_mm256_castsi256_si128(mm));
+    let carry = _mm_unpackhi_epi64(carry, carry);
+    let shifted = _mm256_inserti128_si256::<1>(_mm256_setzero_si256(), carry);
+    _mm256_add_epi16(mm, shifted)
+}
+
+/// Writes the prefix sums of `offsets`, 16 at a time, to `out`.
+///
+/// Only whole chunks of 16 are processed, the sums start at `carry`, and
+/// wrap around on overflow. Returns the sum after the last processed chunk.
+///
+/// # Safety
+///
+/// Requires the `avx2` target feature.
+#[target_feature(enable = "avx2")]
+pub unsafe fn prefix_sums_16<O: ScanOutput, const EXCLUSIVE: bool>(
+    offsets: &[u8],
+    out: &mut [O],
+    mut carry: u64,
+) -> u64 {
+    assert!(out.len() >= offsets.len());
+    let out = out.as_mut_ptr() as *mut u8;
+    for (i, chunk) in offsets.chunks_exact(16).enumerate() {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load, and we
+        // asserted that `out` has room for 16 integers.
+        let widened = _mm256_cvtepu8_epi16(_mm_loadu_si128(chunk.as_ptr() as *const __m128i));
+        let mut mm = scan_16(widened);
+        let total = _mm256_extract_epi16::<15>(mm) as u16 as u64;
+        if EXCLUSIVE {
+            mm = _mm256_sub_epi16(mm, widened);
+        }
+
+        let out = out.add(i * 16 * O::BYTES) as *mut __m256i;
+        match O::BYTES {
+            2 => {
+                let carry = _mm256_set1_epi16(carry as i16);
+                _mm256_storeu_si256(out, _mm256_add_epi16(mm, carry));
+            }
+            4 => {
+                let carry = _mm256_set1_epi32(carry as i32);
+                let lo = _mm256_cvtepu16_epi32(_mm256_castsi256_si128(mm));
+                let hi = _mm256_cvtepu16_epi32(_mm256_extracti128_si256::<1>(mm));
+                _mm256_storeu_si256(out, _mm256_add_epi32(lo, carry));
+                _mm256_storeu_si256(out.add(1), _mm256_add_epi32(hi, carry));
+            }
+            _ => {
+                let carry = _mm256_set1_epi64x(carry as i64);
+                let lo = _mm256_castsi256_si128(mm);
+                let hi = _mm256_extracti128_si256::<1>(mm);
+                let parts = [
+                    lo,
+                    _mm_unpackhi_epi64(lo, lo),
+                    hi,
+                    _mm_unpackhi_epi64(hi, hi),
+                ];
+                for (j, part) in parts.into_iter().enumerate() {
+                    let part = _mm256_cvtepu16_epi64(part);
+                    _mm256_storeu_si256(out.add(j), _mm256_add_epi64(part, carry));
+                }
+            }
+        }
+        carry = carry.wrapping_add(total);
+    }
+    carry
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn prefix_sum_16_inner(offsets: &[u8; 16], lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY: we do an unaligned load of exactly 16 bytes from a 16-byte array.
+    let mm = _mm_loadu_si128(offsets.as_ptr() as *const __m128i);
+    prefix_sum_16_simd(mm, lookup)
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn prefix_sum_16_simd(offsets: __m128i, lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY:
+    // - the prefix sum itself is bounded to `u8::MAX * 16`, which is `< i16::MAX`.
+    // - we check that lookup is `< i16::MAX` to avoid overflow.
 
-    _mm256_store_si256(&mut mm_buf, mm);
-    let u16_buf = &*(&mm_buf as *const __m256i as *const [u16; 16]);
+    let mm = scan_16(_mm256_cvtepu8_epi16(offsets));
 
+    let total = _mm256_extract_epi16::<15>(mm) as u16 as usize;
     if lookup > i16::MAX as usize {
-        return Err(u16_buf[15] as usize);
+        return Err(total);
     }
 
     // compare each i16 with our lookup
@@ -85,12 +216,152 @@ unsafe fn prefix_sum_16_inner(offsets: &[u8; 16], lookup: usize) -> Result<(usiz
     // trailing, because we are dealing with little-endian bytes here
     let idx = mask.trailing_zeros() as usize / 2;
     if idx > 15 {
-        Err(u16_buf[15] as usize)
+        Err(total)
+    } else {
+        Ok((idx, sum_first_bytes(offsets, idx + 1)))
+    }
+}
+
+/// Calculate the Prefix Sum Index of `u16` offsets using AVX2 intrinsics.
+///
+/// See [`prefix_sum_index_u16`](crate::prefix_sum_index_u16) for more information.
+#[target_feature(enable = "avx2")]
+pub unsafe fn prefix_sum_u16_8(offsets: &[u16], lookup: usize) -> Result<(usize, usize), usize> {
+    let mut start = 0;
+    let mut index = 0;
+
+    let mut chunks = offsets.chunks_exact(8);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load.
+        let mm = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        match prefix_sum_u16_8_simd(mm, lookup - start) {
+            Ok((idx, sum)) => return Ok((index + idx, start + sum)),
+            Err(sum) => start += sum,
+        }
+        index += 8;
+    }
+    let remainder = chunks.remainder();
+    if remainder.is_empty() {
+        return Err(start);
+    }
+    // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 16 bytes.
+    let (mm, skip) = load_remainder::<16>(as_bytes(offsets), remainder.len() * 2);
+    match prefix_sum_u16_8_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip / 2, start + sum)),
+        Err(sum) => Err(start + sum),
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn prefix_sum_u16_8_simd(offsets: __m128i, lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY:
+    // - the prefix sum itself is bounded to `u16::MAX * 8`, which is `< i32::MAX`.
+    // - we check that lookup is `< i32::MAX` to avoid overflow.
+
+    // spread the 8xu16 out to 8xi32
+    let mut mm = _mm256_cvtepu16_epi32(offsets);
+
+    // the same prefix sum as for the `u8`s, just with 32-bit elements
+    mm = _mm256_add_epi32(mm, _mm256_slli_si256::<4>(mm));
+    mm = _mm256_add_epi32(mm, _mm256_slli_si256::<8>(mm));
+
+    // broadcast the last 32-bit element of the first 128-lane, and add it to
+    // all the 32-bit elements of the second
+    let carry = _mm_shuffle_epi32::<0b11_11_11_11>(_mm256_castsi256_si128(mm));
+    let shifted = _mm256_inserti128_si256::<1>(_mm256_setzero_si256(), carry);
+    mm = _mm256_add_epi32(mm, shifted);
+
+    let total = _mm256_extract_epi32::<7>(mm) as u32 as usize;
+    if lookup > i32::MAX as usize {
+        return Err(total);
+    }
+
+    // compare each i32 with our lookup
+    let cmp = _mm256_cmpgt_epi32(mm, _mm256_set1_epi32(lookup as i32));
+    // compress the 8*i32 into one bit each
+    let mask = _mm256_movemask_ps(_mm256_castsi256_ps(cmp));
+
+    let idx = mask.trailing_zeros() as usize;
+    if idx > 7 {
+        Err(total)
+    } else {
+        // move the matching element to the front
+        let sum = _mm256_permutevar8x32_epi32(mm, _mm256_set1_epi32(idx as i32));
+        Ok((idx, _mm256_cvtsi256_si32(sum) as u32 as usize))
+    }
+}
+
+/// Calculate the Prefix Sum Index of `u32` offsets using AVX2 intrinsics.
+///
+/// See [`prefix_sum_index_u32`](crate::prefix_sum_index_u32) for more information.
+#[target_feature(enable = "avx2")]
+pub unsafe fn prefix_sum_u32_4(offsets: &[u32], lookup: u64) -> Result<(usize, u64), u64> {
+    let mut start = 0;
+    let mut index = 0;
+
+    let mut chunks = offsets.chunks_exact(4);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load.
+        let mm = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        match prefix_sum_u32_4_simd(mm, lookup - start) {
+            Ok((idx, sum)) => return Ok((index + idx, start + sum)),
+            Err(sum) => start += sum,
+        }
+        index += 4;
+    }
+    let remainder = chunks.remainder();
+    if remainder.is_empty() {
+        return Err(start);
+    }
+    // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 16 bytes.
+    let (mm, skip) = load_remainder::<16>(as_bytes(offsets), remainder.len() * 4);
+    match prefix_sum_u32_4_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip / 4, start + sum)),
+        Err(sum) => Err(start + sum),
+    }
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn prefix_sum_u32_4_simd(offsets: __m128i, lookup: u64) -> Result<(usize, u64), u64> {
+    // SAFETY:
+    // - the prefix sum itself is bounded to `u32::MAX * 4`, which is `< i64::MAX`.
+    // - we check that lookup is `< i64::MAX` to avoid overflow.
+
+    // spread the 4xu32 out to 4xi64
+    let mut mm = _mm256_cvtepu32_epi64(offsets);
+
+    // the prefix sum within each 128-lane, which only has 2 elements
+    mm = _mm256_add_epi64(mm, _mm256_slli_si256::<8>(mm));
+
+    // broadcast the last 64-bit element of the first 128-lane, and add it to
+    // both 64-bit elements of the second
+    let carry = _mm_unpackhi_epi64(_mm256_castsi256_si128(mm), _mm256_castsi256_si128(mm));
+    let shifted = _mm256_inserti128_si256::<1>(_mm256_setzero_si256(), carry);
+    mm = _mm256_add_epi64(mm, shifted);
+
+    let total = _mm256_extract_epi64::<3>(mm) as u64;
+    if lookup > i64::MAX as u64 {
+        return Err(total);
+    }
+
+    // compare each i64 with our lookup
+    let cmp = _mm256_cmpgt_epi64(mm, _mm256_set1_epi64x(lookup as i64));
+    // compress the 4*i64 into one bit each
+    let mask = _mm256_movemask_pd(_mm256_castsi256_pd(cmp));
+
+    let idx = mask.trailing_zeros() as usize;
+    if idx > 3 {
+        Err(total)
     } else {
-        Ok((idx, u16_buf[idx] as usize))
+        // move the two 32-bit halves of the matching element to the front
+        let lo = idx as i32 * 2;
+        let sum = _mm256_permutevar8x32_epi32(mm, _mm256_setr_epi32(lo, lo + 1, 0, 0, 0, 0, 0, 0));
+        Ok((idx, _mm_cvtsi128_si64(_mm256_castsi256_si128(sum)) as u64))
     }
 }
 
+#[cfg(test)]
+use crate::fallback::prefix_sum_scalar;
 #[cfg(test)]
 use crate::prefix_sum_fallback;
 
@@ -149,3 +420,88 @@ fn test_simd_16() {
         Err(255 * 16)
     );
 }
+
+#[test]
+fn test_sum_64() {
+    if !is_x86_feature_detected!("avx2") {
+        return;
+    }
+    let mut offsets = [0; 64];
+    assert_eq!(unsafe { sum_64(&offsets) }, 0);
+    for (i, offset) in offsets.iter_mut().enumerate() {
+        *offset = i as u8;
+    }
+    assert_eq!(unsafe { sum_64(&offsets) }, 63 * 64 / 2);
+    assert_eq!(unsafe { sum_64(&[255; 64]) }, 255 * 64);
+
+    let offsets: Vec<u8> = (0..=255).chain(0..=255).collect();
+    for from in [0, 1, 31, 32, 33, 100] {
+        for to in [from, from + 1, from + 31, from + 32, from + 65, 512] {
+            let slice = &offsets[from..to];
+            assert_eq!(
+                unsafe { sum(slice) },
+                slice.iter().map(|o| *o as usize).sum()
+            );
+        }
+    }
+}
+
+#[test]
+fn test_simd_u16() {
+    if !is_x86_feature_detected!("avx2") {
+        return;
+    }
+    let offsets: [u16; 8] = [
+        0,      //      0
+        1,      //      1
+        300,    //    301
+        4,      //    305
+        65_535, // 65_840
+        1,      // 65_841
+        2,      // 65_843
+        9,      // 65_852
+    ];
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    for lookup in [0, 1, 300, 301, 305, 65_839, 65_840, 65_851, 65_852, 70_000] {
+        assert_eq!(
+            unsafe { prefix_sum_u16_8_simd(mm, lookup) },
+            prefix_sum_scalar(&offsets, lookup)
+        );
+    }
+
+    let offsets = [u16::MAX; 8];
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    assert_eq!(
+        unsafe { prefix_sum_u16_8_simd(mm, 1 << 34) },
+        Err(u16::MAX as usize * 8)
+    );
+}
+
+#[test]
+fn test_simd_u32() {
+    if !is_x86_feature_detected!("avx2") {
+        return;
+    }
+    let offsets: [u32; 4] = [
+        70_000,        //        70_000
+        0,             //        70_000
+        u32::MAX,      // 4_295_037_295
+        3_000_000_000, // 7_295_037_295
+    ];
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    for lookup in [
+        0,
+        69_999,
+        70_000,
+        4_295_037_294,
+        4_295_037_295,
+        7_295_037_294,
+        7_295_037_295,
+        u64::MAX,
+    ] {
+        assert_eq!(
+            unsafe { prefix_sum_u32_4_simd(mm, lookup) },
+            prefix_sum_scalar(&offsets, lookup)
+        );
+    }
+}
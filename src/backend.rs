@@ -0,0 +1,282 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::fallback::prefix_sum_scalar;
+use crate::scan::{prefix_sums_scalar, ScanOutput};
+#[cfg(target_arch = "x86_64")]
+use crate::{avx, avx2, sse2};
+use crate::{prefix_sum_fallback, swar, OffsetElement};
+
+/// The implementation used to calculate the Prefix Sum Index.
+///
+/// [`prefix_sum_index`](crate::prefix_sum_index) picks a backend via
+/// [`Backend::detect`]. Use [`prefix_sum_index_with`] to pin a specific one.
+///
+/// All backends return identical results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum Backend {
+    /// Plain scalar code that works everywhere.
+    Scalar = 1,
+    /// 8 lanes at a time, using bit-tricks on `u64` words. Works everywhere.
+    Swar = 2,
+    /// 16 lanes at a time, using SSE2 intrinsics. Only available on `x86_64`,
+    /// where it is part of the baseline and thus always supported.
+    Sse2 = 3,
+    /// 8 lanes at a time, using SSE4.1 intrinsics. Only available on `x86_64`.
+    Sse41 = 4,
+    /// 8 lanes at a time, using AVX intrinsics. Only available on `x86_64`.
+    Avx = 5,
+    /// 16 lanes at a time, using AVX2 intrinsics. Only available on `x86_64`.
+    Avx2 = 6,
+}
+
+/// The cached result of [`Backend::detect`], `0` if detection has not run yet.
+static DETECTED: AtomicU8 = AtomicU8::new(0);
+
+impl Backend {
+    /// All the backends, ordered by the instruction set extension they need,
+    /// from none to the newest.
+    ///
+    /// This is not strictly ordered by throughput: [`Sse2`](Self::Sse2) scans
+    /// 16 lanes at a time, but comes before the 8-lane [`Sse41`](Self::Sse41)
+    /// and [`Avx`](Self::Avx), which all perform about the same.
+    pub const ALL: &'static [Backend] = &[
+        Self::Scalar,
+        Self::Swar,
+        Self::Sse2,
+        Self::Sse41,
+        Self::Avx,
+        Self::Avx2,
+    ];
+
+    /// Returns the backend with the newest instruction set extension that is
+    /// supported by the current CPU.
+    ///
+    /// Feature detection only runs on the first call, its result is cached.
+    #[inline]
+    pub fn detect() -> Self {
+        match DETECTED.load(Ordering::Relaxed) {
+            1 => Self::Scalar,
+            2 => Self::Swar,
+            3 => Self::Sse2,
+            4 => Self::Sse41,
+            5 => Self::Avx,
+            6 => Self::Avx2,
+            _ => {
+                let backend = Self::detect_uncached();
+                // racing threads will all detect and store the same value
+                DETECTED.store(backend as u8, Ordering::Relaxed);
+                backend
+            }
+        }
+    }
+
+    #[cold]
+    fn detect_uncached() -> Self {
+        // the last one in `ALL` needs the newest extension, and `Avx2` is the
+        // fastest where it is supported.
+        Self::available().last().unwrap_or(Self::Scalar)
+    }
+
+    /// Returns all the backends supported by the current CPU, in the order of
+    /// [`ALL`](Self::ALL).
+    pub fn available() -> impl Iterator<Item = Backend> {
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(|backend| backend.is_supported())
+    }
+
+    /// Returns `true` if this backend can be used on the current CPU.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Scalar | Self::Swar => true,
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse2 => true,
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse41 => is_x86_feature_detected!("sse4.1"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx => is_x86_feature_detected!("avx"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => false,
+        }
+    }
+
+    /// Calculate the Prefix Sum Index using this backend.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[inline]
+    pub(crate) unsafe fn prefix_sum_index_unchecked(
+        self,
+        offsets: &[u8],
+        lookup: usize,
+    ) -> Result<(usize, usize), usize> {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => avx2::prefix_sum_16(offsets, lookup),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx => avx::prefix_sum_8(offsets, lookup),
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse41 => avx::prefix_sum_8_sse41(offsets, lookup),
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse2 => sse2::prefix_sum_16(offsets, lookup),
+            Self::Swar => swar::prefix_sum_8(offsets, lookup),
+            _ => prefix_sum_fallback(offsets, lookup),
+        }
+    }
+
+    /// Sums up all the `offsets` using this backend.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[inline]
+    pub(crate) unsafe fn sum_unchecked(self, offsets: &[u8]) -> usize {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => avx2::sum(offsets),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx | Self::Sse41 | Self::Sse2 => sse2::sum(offsets),
+            Self::Swar => swar::sum(offsets),
+            _ => offsets.iter().map(|offset| *offset as usize).sum(),
+        }
+    }
+
+    /// Writes the prefix sums of `offsets` to `out` using this backend.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[inline]
+    pub(crate) unsafe fn prefix_sums_unchecked<O: ScanOutput, const EXCLUSIVE: bool>(
+        self,
+        offsets: &[u8],
+        out: &mut [O],
+    ) {
+        let carry = match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => avx2::prefix_sums_16::<O, EXCLUSIVE>(offsets, out, 0),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx | Self::Sse41 | Self::Sse2 => {
+                sse2::prefix_sums_16::<O, EXCLUSIVE>(offsets, out, 0)
+            }
+            _ => 0,
+        };
+        let done = match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 | Self::Avx | Self::Sse41 | Self::Sse2 => offsets.len() / 16 * 16,
+            _ => 0,
+        };
+        prefix_sums_scalar::<O, EXCLUSIVE>(&offsets[done..], &mut out[done..], carry);
+    }
+
+    /// Calculate the Prefix Sum Index of `u16` offsets using this backend.
+    ///
+    /// Backends without a dedicated `u16` implementation use the best one
+    /// they support.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[inline]
+    pub(crate) unsafe fn prefix_sum_index_u16_unchecked(
+        self,
+        offsets: &[u16],
+        lookup: usize,
+    ) -> Result<(usize, usize), usize> {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => avx2::prefix_sum_u16_8(offsets, lookup),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx | Self::Sse41 | Self::Sse2 => sse2::prefix_sum_u16_8(offsets, lookup),
+            _ => prefix_sum_scalar(offsets, lookup),
+        }
+    }
+
+    /// Calculate the Prefix Sum Index of `u32` offsets using this backend.
+    ///
+    /// Backends without a dedicated `u32` implementation use the best one
+    /// they support.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[inline]
+    pub(crate) unsafe fn prefix_sum_index_u32_unchecked(
+        self,
+        offsets: &[u32],
+        lookup: u64,
+    ) -> Result<(usize, u64), u64> {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => avx2::prefix_sum_u32_4(offsets, lookup),
+            _ => prefix_sum_scalar(offsets, lookup),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Scalar => "scalar",
+            Self::Swar => "swar",
+            Self::Sse2 => "sse2",
+            Self::Sse41 => "sse4.1",
+            Self::Avx => "avx",
+            Self::Avx2 => "avx2",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The error returned when a [`Backend`] is not supported by the current CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnsupportedBackend(pub Backend);
+
+impl fmt::Display for UnsupportedBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the `{}` backend is not supported by this CPU", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedBackend {}
+
+/// Calculate the Prefix Sum Index using a specific [`Backend`].
+///
+/// Returns [`Err(UnsupportedBackend)`](UnsupportedBackend) if the backend can
+/// not be used on the current CPU, otherwise the result is the same as for
+/// [`prefix_sum_index`](crate::prefix_sum_index).
+///
+/// For [`OffsetElement`]s that have no dedicated implementation for the
+/// requested backend, the best one it supports is used instead.
+///
+/// # Examples
+///
+/// ```
+/// use psy::{prefix_sum_index_with, Backend};
+///
+/// let offsets: [u8; 5] = [0, 1, 0, 4, 8];
+/// assert_eq!(prefix_sum_index_with(Backend::Scalar, &offsets, 1), Ok(Ok((3, 5))));
+///
+/// for backend in Backend::available() {
+///     assert_eq!(prefix_sum_index_with(backend, &offsets, 20), Ok(Err(13)));
+/// }
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn prefix_sum_index_with<T: OffsetElement>(
+    backend: Backend,
+    offsets: &[T],
+    lookup: T::Sum,
+) -> Result<Result<(usize, T::Sum), T::Sum>, UnsupportedBackend> {
+    if !backend.is_supported() {
+        return Err(UnsupportedBackend(backend));
+    }
+    // SAFETY: we just checked that the backend is supported.
+    Ok(unsafe { T::prefix_sum_index_unchecked(backend, offsets, lookup) })
+}
@@ -0,0 +1,86 @@
+use crate::{Cursor, OffsetElement};
+
+#[cfg(test)]
+use crate::prefix_sum_index;
+
+/// Calculate the Prefix Sum Index for many `lookups` at once.
+///
+/// The result for each of the `lookups` is written to the same position in
+/// `out`, and is the same as calling
+/// [`prefix_sum_index_of`](crate::prefix_sum_index_of) for it.
+///
+/// Instead of searching from the start of `offsets` for each lookup, this
+/// does a single forward sweep over `offsets` using a [`Cursor`], resuming
+/// each search where the previous one left off. That requires the `lookups`
+/// to be sorted. Unsorted `lookups` are supported as well, but are sorted
+/// internally, which needs an extra allocation.
+///
+/// # Panics
+///
+/// Panics if `lookups` and `out` have different lengths.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sum_index_batch;
+///
+/// let offsets: [u8; 4] = [12, 0, 30, 7];
+/// let lookups = [0, 11, 12, 48, 49];
+/// let mut out = [Err(0); 5];
+///
+/// prefix_sum_index_batch(&offsets, &lookups, &mut out);
+/// assert_eq!(out, [Ok((0, 12)), Ok((0, 12)), Ok((2, 42)), Ok((3, 49)), Err(49)]);
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn prefix_sum_index_batch<T: OffsetElement>(
+    offsets: &[T],
+    lookups: &[T::Sum],
+    out: &mut [Result<(usize, T::Sum), T::Sum>],
+) {
+    assert_eq!(
+        lookups.len(),
+        out.len(),
+        "`lookups` and `out` need to have the same length"
+    );
+
+    let mut cursor = Cursor::new(offsets);
+    if lookups.windows(2).all(|w| w[0] <= w[1]) {
+        for (lookup, out) in lookups.iter().zip(out) {
+            *out = cursor.seek(*lookup);
+        }
+    } else {
+        let mut order: Vec<usize> = (0..lookups.len()).collect();
+        order.sort_unstable_by_key(|i| lookups[*i]);
+        for i in order {
+            out[i] = cursor.seek(lookups[i]);
+        }
+    }
+}
+
+#[test]
+fn test_batch() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 15, 16, 17, 100, 1_000] {
+        let offsets: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+
+        let mut lookups: Vec<usize> = (0..300).map(|_| rng.gen_range(0..total + 5)).collect();
+        let mut out = vec![Err(0); lookups.len()];
+
+        // unsorted
+        prefix_sum_index_batch(&offsets, &lookups, &mut out);
+        for (lookup, result) in lookups.iter().zip(&out) {
+            assert_eq!(*result, prefix_sum_index(&offsets, *lookup));
+        }
+
+        // sorted
+        lookups.sort();
+        prefix_sum_index_batch(&offsets, &lookups, &mut out);
+        for (lookup, result) in lookups.iter().zip(&out) {
+            assert_eq!(*result, prefix_sum_index(&offsets, *lookup));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{prefix_sum_at, prefix_sum_index_of};
+
+/// The unit columns are counted in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ColumnUnit {
+    /// UTF-8 bytes, which is the same as the byte offset within the line.
+    #[default]
+    Utf8,
+    /// UTF-16 code units, as used by language servers and JavaScript.
+    Utf16,
+    /// Unicode code points, aka Rust `char`s.
+    CodePoint,
+}
+
+/// The non-ASCII characters of a single line.
+///
+/// The line is split up into segments, each consisting of a run of ASCII
+/// characters followed by a single non-ASCII character. The widths of these
+/// segments are stored in each of the [`ColumnUnit`]s, so converting a
+/// column is a prefix sum search in the source unit, followed by a prefix sum
+/// in the target unit. Any ASCII characters after the last segment have the
+/// same width in all the units.
+#[derive(Clone, Debug)]
+pub(crate) struct WideChars {
+    utf8: Vec<u32>,
+    utf16: Vec<u32>,
+    code_points: Vec<u32>,
+}
+
+impl WideChars {
+    /// Collects the non-ASCII characters in `line`, returning `None` if there
+    /// are none.
+    ///
+    /// The `line` does not have to be valid UTF-8, in which case the
+    /// resulting columns are unspecified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a run of ASCII characters does not fit into a `u32`.
+    pub fn new(line: &[u8]) -> Option<Self> {
+        if line.is_ascii() {
+            return None;
+        }
+
+        let mut wide = Self {
+            utf8: vec![],
+            utf16: vec![],
+            code_points: vec![],
+        };
+        let mut run = 0u32;
+        for byte in line {
+            let (utf8, utf16) = match byte {
+                0x00..=0x7F => {
+                    run = run
+                        .checked_add(1)
+                        .expect("ASCII run has to fit into a `u32`");
+                    continue;
+                }
+                // continuation bytes are counted along with their leading byte
+                0x80..=0xBF => continue,
+                0xC0..=0xDF => (2, 1),
+                0xE0..=0xEF => (3, 1),
+                // these need a surrogate pair in UTF-16
+                0xF0..=0xFF => (4, 2),
+            };
+            wide.utf8.push(run + utf8);
+            wide.utf16.push(run + utf16);
+            wide.code_points.push(run + 1);
+            run = 0;
+        }
+        Some(wide)
+    }
+
+    fn widths(&self, unit: ColumnUnit) -> &[u32] {
+        match unit {
+            ColumnUnit::Utf8 => &self.utf8,
+            ColumnUnit::Utf16 => &self.utf16,
+            ColumnUnit::CodePoint => &self.code_points,
+        }
+    }
+
+    /// Converts `col` from one unit to another.
+    ///
+    /// Returns `None` if `col` points into the middle of a character. The
+    /// `col` is not checked against the length of the line.
+    pub fn convert(&self, col: usize, from: ColumnUnit, to: ColumnUnit) -> Option<usize> {
+        let (source, target) = (self.widths(from), self.widths(to));
+        match prefix_sum_index_of(source, col as u64) {
+            Ok((segment, end)) => {
+                let start = end - source[segment] as u64;
+                let within = col - start as usize;
+                // the run of ASCII characters before the wide one
+                let run = self.code_points[segment] as usize - 1;
+                if within > run {
+                    return None;
+                }
+                Some(prefix_sum_at(target, segment) as usize + within)
+            }
+            Err(total) => Some(prefix_sum_at(target, target.len()) as usize + col - total as usize),
+        }
+    }
+}
+
+#[test]
+fn test_wide_chars() {
+    assert!(WideChars::new(b"abc\n").is_none());
+
+    let line = "aä€b𝄞\n";
+    let wide = WideChars::new(line.as_bytes()).unwrap();
+
+    let mut utf16 = 0;
+    for (code_point, (utf8, c)) in line.char_indices().chain([(line.len(), ' ')]).enumerate() {
+        let cols = [
+            (ColumnUnit::Utf8, utf8),
+            (ColumnUnit::Utf16, utf16),
+            (ColumnUnit::CodePoint, code_point),
+        ];
+        for (from, from_col) in cols {
+            for (to, to_col) in cols {
+                assert_eq!(wide.convert(from_col, from, to), Some(to_col));
+            }
+        }
+        utf16 += c.len_utf16();
+    }
+
+    // the middle of `ä`, `€` and `𝄞`
+    for utf8 in [2, 4, 5, 8, 9, 10] {
+        assert_eq!(
+            wide.convert(utf8, ColumnUnit::Utf8, ColumnUnit::Utf16),
+            None
+        );
+    }
+    assert_eq!(
+        wide.convert(5, ColumnUnit::Utf16, ColumnUnit::CodePoint),
+        None
+    );
+}
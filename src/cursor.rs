@@ -0,0 +1,128 @@
+use crate::{prefix_sum_index_of, OffsetElement};
+
+/// A cursor over `offsets`, which resumes each search where the previous one
+/// left off.
+///
+/// When the looked up positions are (mostly) increasing, for example when
+/// walking through a file from front to back, each [`seek`](Cursor::seek) only
+/// scans the entries between the previous and the current position, instead
+/// of starting over at the first entry like
+/// [`prefix_sum_index`](crate::prefix_sum_index) does.
+///
+/// Seeking backwards is supported as well, and walks back entry by entry.
+///
+/// # Examples
+///
+/// ```
+/// use psy::Cursor;
+///
+/// let offsets: [u8; 4] = [12, 0, 30, 7];
+/// let mut cursor = Cursor::new(&offsets);
+///
+/// assert_eq!(cursor.seek(5), Ok((0, 12)));
+/// assert_eq!(cursor.seek(20), Ok((2, 42)));
+/// assert_eq!(cursor.seek(45), Ok((3, 49)));
+/// assert_eq!(cursor.seek(60), Err(49));
+/// // going backwards
+/// assert_eq!(cursor.seek(13), Ok((2, 42)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cursor<'a, T: OffsetElement> {
+    offsets: &'a [T],
+    /// The index of the entry the previous lookup was found in, or the
+    /// length of `offsets` if it was out of bounds.
+    index: usize,
+    /// The prefix sum *before* the entry at `index`.
+    start: T::Sum,
+}
+
+impl<'a, T: OffsetElement> Cursor<'a, T> {
+    /// Creates a new cursor, positioned at the first entry.
+    pub fn new(offsets: &'a [T]) -> Self {
+        Self {
+            offsets,
+            index: 0,
+            start: T::Sum::default(),
+        }
+    }
+
+    /// The `offsets` this cursor is searching.
+    pub fn offsets(&self) -> &'a [T] {
+        self.offsets
+    }
+
+    /// The index of the entry the cursor is positioned at.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The prefix sum *before* the entry the cursor is positioned at.
+    pub fn start(&self) -> T::Sum {
+        self.start
+    }
+
+    /// Positions the cursor at the first entry again.
+    pub fn reset(&mut self) {
+        self.index = 0;
+        self.start = T::Sum::default();
+    }
+
+    /// Calculate the Prefix Sum Index, starting from the current position.
+    ///
+    /// The result is the same as for [`prefix_sum_index_of`], and the cursor
+    /// is positioned at the found entry. If `lookup` is out of bounds, the
+    /// cursor is positioned after the last entry.
+    pub fn seek(&mut self, lookup: T::Sum) -> Result<(usize, T::Sum), T::Sum> {
+        // walk back until we are at an entry that starts at or before `lookup`
+        while lookup < self.start {
+            self.index -= 1;
+            self.start = self.start - self.offsets[self.index].widen();
+        }
+
+        match prefix_sum_index_of(&self.offsets[self.index..], lookup - self.start) {
+            Ok((idx, sum)) => {
+                let end = self.start + sum;
+                self.index += idx;
+                self.start = end - self.offsets[self.index].widen();
+                Ok((self.index, end))
+            }
+            Err(sum) => {
+                self.index = self.offsets.len();
+                self.start = self.start + sum;
+                Err(self.start)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cursor() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 15, 16, 17, 100, 1_000] {
+        let offsets: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+
+        let mut cursor = Cursor::new(&offsets);
+        // random jumps in both directions
+        for _ in 0..300 {
+            let lookup = rng.gen_range(0..total + 5);
+            assert_eq!(
+                cursor.seek(lookup),
+                crate::prefix_sum_index(&offsets, lookup)
+            );
+        }
+        // walking forward and backward
+        for lookup in (0..total + 5).chain((0..total + 5).rev()) {
+            assert_eq!(
+                cursor.seek(lookup),
+                crate::prefix_sum_index(&offsets, lookup)
+            );
+        }
+
+        cursor.reset();
+        assert_eq!((cursor.index(), cursor.start()), (0, 0));
+    }
+}
@@ -0,0 +1,142 @@
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::{Add, Sub};
+
+use crate::backend::Backend;
+use crate::fallback::prefix_sum_scalar;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// An integer type that can be used as an element of the `offsets`.
+///
+/// | Element | [`Sum`](OffsetElement::Sum) | Implementations        |
+/// |---------|-----------------------------|------------------------|
+/// | `u8`    | `usize`                     | all [`Backend`]s       |
+/// | `u16`   | `usize`                     | SSE2, AVX2 and scalar  |
+/// | `u32`   | `u64`                       | AVX2 and scalar        |
+/// | `u64`   | `u64`                       | scalar                 |
+///
+/// This trait is sealed, and can not be implemented outside of this crate.
+pub trait OffsetElement: Copy + Debug + sealed::Sealed {
+    /// The type used for prefix sums and lookups.
+    type Sum: Copy
+        + Debug
+        + Default
+        + Eq
+        + Ord
+        + Hash
+        + From<u8>
+        + Add<Output = Self::Sum>
+        + Sub<Output = Self::Sum>;
+
+    /// Widens the element to its [`Sum`](OffsetElement::Sum) type.
+    fn widen(self) -> Self::Sum;
+
+    /// Calculate the Prefix Sum Index using the given backend.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[doc(hidden)]
+    unsafe fn prefix_sum_index_unchecked(
+        backend: Backend,
+        offsets: &[Self],
+        lookup: Self::Sum,
+    ) -> Result<(usize, Self::Sum), Self::Sum>;
+
+    /// Sums up all the `offsets` using the given backend.
+    ///
+    /// # Safety
+    ///
+    /// The backend has to be [supported](Backend::is_supported) by the current CPU.
+    #[doc(hidden)]
+    unsafe fn sum_unchecked(backend: Backend, offsets: &[Self]) -> Self::Sum {
+        let _ = backend;
+        offsets
+            .iter()
+            .fold(Self::Sum::default(), |sum, offset| sum + offset.widen())
+    }
+}
+
+impl OffsetElement for u8 {
+    type Sum = usize;
+
+    #[inline]
+    fn widen(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    unsafe fn prefix_sum_index_unchecked(
+        backend: Backend,
+        offsets: &[u8],
+        lookup: usize,
+    ) -> Result<(usize, usize), usize> {
+        backend.prefix_sum_index_unchecked(offsets, lookup)
+    }
+
+    #[inline]
+    unsafe fn sum_unchecked(backend: Backend, offsets: &[u8]) -> usize {
+        backend.sum_unchecked(offsets)
+    }
+}
+
+impl OffsetElement for u16 {
+    type Sum = usize;
+
+    #[inline]
+    fn widen(self) -> usize {
+        self as usize
+    }
+
+    #[inline]
+    unsafe fn prefix_sum_index_unchecked(
+        backend: Backend,
+        offsets: &[u16],
+        lookup: usize,
+    ) -> Result<(usize, usize), usize> {
+        backend.prefix_sum_index_u16_unchecked(offsets, lookup)
+    }
+}
+
+impl OffsetElement for u32 {
+    type Sum = u64;
+
+    #[inline]
+    fn widen(self) -> u64 {
+        self as u64
+    }
+
+    #[inline]
+    unsafe fn prefix_sum_index_unchecked(
+        backend: Backend,
+        offsets: &[u32],
+        lookup: u64,
+    ) -> Result<(usize, u64), u64> {
+        backend.prefix_sum_index_u32_unchecked(offsets, lookup)
+    }
+}
+
+impl OffsetElement for u64 {
+    type Sum = u64;
+
+    #[inline]
+    fn widen(self) -> u64 {
+        self
+    }
+
+    #[inline]
+    unsafe fn prefix_sum_index_unchecked(
+        _backend: Backend,
+        offsets: &[u64],
+        lookup: u64,
+    ) -> Result<(usize, u64), u64> {
+        prefix_sum_scalar(offsets, lookup)
+    }
+}
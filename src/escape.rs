@@ -0,0 +1,222 @@
+use crate::{prefix_sum_index, range_sum};
+
+/// The byte marking an offset stored in the side table.
+const ESCAPE: u8 = u8::MAX;
+
+/// An offset that does not fit into the dense bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Escape {
+    /// The index of the offset.
+    index: usize,
+    /// The actual offset.
+    value: usize,
+    /// The prefix sum up to and including this offset.
+    end: usize,
+}
+
+/// A table of offsets that mostly fit into a `u8`.
+///
+/// Each offset is stored as a single byte. Offsets of `255` or more are
+/// stored as an escape byte of `255`, with their actual value in a sparse
+/// side table. This keeps the table at about one byte per entry, without
+/// limiting the size of the offsets.
+///
+/// Lookups binary-search the side table for the run of dense bytes between
+/// two escapes, and use [`prefix_sum_index`] on that run.
+///
+/// # Examples
+///
+/// ```
+/// use psy::EscapedOffsets;
+///
+/// let offsets: EscapedOffsets = [12, 0, 70_000, 30, 255].into_iter().collect();
+///
+/// assert_eq!(offsets.bytes(), &[12, 0, 255, 30, 255]);
+/// assert_eq!(offsets.get(2), Some(70_000));
+/// assert_eq!(offsets.prefix_sum_index(11), Ok((0, 12)));
+/// assert_eq!(offsets.prefix_sum_index(12), Ok((2, 70_012)));
+/// assert_eq!(offsets.prefix_sum_index(70_012), Ok((3, 70_042)));
+/// assert_eq!(offsets.prefix_sum_index(70_042), Ok((4, 70_297)));
+/// assert_eq!(offsets.prefix_sum_index(70_297), Err(70_297));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EscapedOffsets {
+    bytes: Vec<u8>,
+    escapes: Vec<Escape>,
+    total: usize,
+}
+
+impl EscapedOffsets {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The dense bytes, with `255` marking the escaped offsets.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The number of offsets that are stored in the side table.
+    pub fn escaped_len(&self) -> usize {
+        self.escapes.len()
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Appends an offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum of all the offsets does not fit into a `usize`.
+    pub fn push(&mut self, offset: usize) {
+        self.total = self
+            .total
+            .checked_add(offset)
+            .expect("the sum of the offsets has to fit into a `usize`");
+        match u8::try_from(offset) {
+            Ok(byte) if byte != ESCAPE => self.bytes.push(byte),
+            _ => {
+                self.escapes.push(Escape {
+                    index: self.bytes.len(),
+                    value: offset,
+                    end: self.total,
+                });
+                self.bytes.push(ESCAPE);
+            }
+        }
+    }
+
+    /// Returns the offset at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<usize> {
+        match *self.bytes.get(index)? {
+            ESCAPE => {
+                let escape = self.escapes.binary_search_by_key(&index, |e| e.index);
+                Some(self.escapes[escape.ok()?].value)
+            }
+            byte => Some(byte as usize),
+        }
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, index: usize) -> usize {
+        assert!(index <= self.bytes.len());
+        let escape = self.escapes.partition_point(|e| e.index < index);
+        let (from, start) = self.run_start(escape);
+        start + range_sum(&self.bytes, from, index)
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as [`prefix_sum_index`] would for the
+    /// actual offsets.
+    pub fn prefix_sum_index(&self, lookup: usize) -> Result<(usize, usize), usize> {
+        // the first escape that ends after `lookup`, all the offsets in the
+        // run of bytes before it are not escaped.
+        let escape = self.escapes.partition_point(|e| e.end <= lookup);
+        let (from, start) = self.run_start(escape);
+        let to = match self.escapes.get(escape) {
+            Some(escape) => escape.index,
+            None => self.bytes.len(),
+        };
+
+        match prefix_sum_index(&self.bytes[from..to], lookup - start) {
+            Ok((index, sum)) => Ok((from + index, start + sum)),
+            Err(sum) => match self.escapes.get(escape) {
+                Some(escape) => Ok((escape.index, escape.end)),
+                None => Err(start + sum),
+            },
+        }
+    }
+
+    /// The index and prefix sum at the start of the run of bytes following
+    /// the escape *before* `escape`.
+    fn run_start(&self, escape: usize) -> (usize, usize) {
+        match escape.checked_sub(1) {
+            Some(previous) => {
+                let previous = &self.escapes[previous];
+                (previous.index + 1, previous.end)
+            }
+            None => (0, 0),
+        }
+    }
+}
+
+impl Extend<usize> for EscapedOffsets {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|offset| self.push(offset));
+    }
+}
+
+impl FromIterator<usize> for EscapedOffsets {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut offsets = Self::new();
+        offsets.extend(iter);
+        offsets
+    }
+}
+
+#[test]
+fn test_escaped() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let offsets = EscapedOffsets::new();
+    assert_eq!(offsets.prefix_sum_index(0), Err(0));
+    assert_eq!(offsets.prefix_sum_at(0), 0);
+    assert_eq!(offsets.get(0), None);
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [1, 2, 16, 17, 100, 1_000] {
+        for escape_ratio in [0.0, 0.01, 0.2, 1.0] {
+            let values: Vec<usize> = (0..len)
+                .map(|_| match rng.gen_bool(escape_ratio) {
+                    true => rng.gen_range(254..1_000),
+                    false => rng.gen_range(0..8),
+                })
+                .collect();
+            let offsets: EscapedOffsets = values.iter().copied().collect();
+            let wide: Vec<u64> = values.iter().map(|v| *v as u64).collect();
+            let total = values.iter().sum::<usize>();
+            assert_eq!(offsets.total(), total);
+
+            for (index, value) in values.iter().enumerate() {
+                assert_eq!(offsets.get(index), Some(*value));
+            }
+            for lookup in (0..total + 2).step_by(total / 300 + 1).chain([total]) {
+                let expected = crate::prefix_sum_index_of(&wide, lookup as u64)
+                    .map(|(index, sum)| (index, sum as usize))
+                    .map_err(|sum| sum as usize);
+                assert_eq!(offsets.prefix_sum_index(lookup), expected);
+            }
+            for index in 0..=len {
+                let expected = values[..index].iter().sum::<usize>();
+                assert_eq!(offsets.prefix_sum_at(index), expected);
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "the sum of the offsets has to fit into a `usize`")]
+fn test_escaped_overflow() {
+    let mut offsets: EscapedOffsets = [1, usize::MAX - 1].into_iter().collect();
+    offsets.push(1);
+}
@@ -1,3 +1,12 @@
+use crate::OffsetElement;
+
+/// Calculate the Prefix Sum Index using plain scalar code.
+///
+/// This is available on every target, and is used by
+/// [`prefix_sum_index`](crate::prefix_sum_index) when no SIMD implementation
+/// is supported by the current CPU.
+///
+/// See [`crate level docs`](crate) for more information.
 pub fn prefix_sum_fallback(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
     let mut start = 0;
     for (i, offset) in offsets.iter().enumerate() {
@@ -10,6 +19,22 @@ pub fn prefix_sum_fallback(offsets: &[u8], lookup: usize) -> Result<(usize, usiz
     Err(start)
 }
 
+/// Calculate the Prefix Sum Index of any [`OffsetElement`] using plain scalar code.
+pub(crate) fn prefix_sum_scalar<T: OffsetElement>(
+    offsets: &[T],
+    lookup: T::Sum,
+) -> Result<(usize, T::Sum), T::Sum> {
+    let mut start = T::Sum::default();
+    for (i, offset) in offsets.iter().enumerate() {
+        let current = start + offset.widen();
+        if current > lookup {
+            return Ok((i, current));
+        }
+        start = current;
+    }
+    Err(start)
+}
+
 #[test]
 fn test_fallback() {
     let offsets = [
@@ -22,4 +47,12 @@ fn test_fallback() {
     assert_eq!(prefix_sum_fallback(&offsets, 1,), Ok((2, 5)));
     assert_eq!(prefix_sum_fallback(&offsets, 7,), Ok((3, 13)));
     assert_eq!(prefix_sum_fallback(&offsets, 16), Err(13));
+
+    for lookup in [0, 1, 7, 16] {
+        let offsets_u64 = offsets.map(u64::from);
+        let expected = prefix_sum_fallback(&offsets, lookup)
+            .map(|(idx, sum)| (idx, sum as u64))
+            .map_err(|sum| sum as u64);
+        assert_eq!(prefix_sum_scalar(&offsets_u64, lookup as u64), expected);
+    }
 }
@@ -0,0 +1,219 @@
+/// An updatable table of `u8` offsets, backed by a Fenwick tree.
+///
+/// Unlike the functions working on a plain `&[u8]`, this supports changing
+/// individual offsets in `O(log n)`, while keeping both
+/// [`prefix_sum_at`](Self::prefix_sum_at) and
+/// [`prefix_sum_index`](Self::prefix_sum_index) at `O(log n)` as well.
+///
+/// The tree needs one `usize` per entry, next to the offsets themselves.
+///
+/// # Examples
+///
+/// ```
+/// use psy::FenwickIndex;
+///
+/// let mut index = FenwickIndex::new(vec![0, 1, 0, 4, 8, 1]);
+/// assert_eq!(index.prefix_sum_index(5), Ok((4, 13)));
+///
+/// index.set(2, 10);
+/// assert_eq!(index.prefix_sum_at(3), 11);
+/// assert_eq!(index.prefix_sum_index(5), Ok((2, 11)));
+///
+/// index.add(2, -10);
+/// assert_eq!(index.prefix_sum_index(5), Ok((4, 13)));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FenwickIndex {
+    offsets: Vec<u8>,
+    /// The 1-based tree, `tree[i]` holds the sum of the `i & i.wrapping_neg()`
+    /// offsets ending at `offsets[i - 1]`.
+    tree: Vec<usize>,
+}
+
+impl FenwickIndex {
+    /// Creates a new index, in `O(n)`.
+    pub fn new(offsets: Vec<u8>) -> Self {
+        let mut tree = vec![0; offsets.len() + 1];
+        for i in 1..tree.len() {
+            tree[i] += offsets[i - 1] as usize;
+            let parent = i + lowest_bit(i);
+            if parent < tree.len() {
+                tree[parent] += tree[i];
+            }
+        }
+        Self { offsets, tree }
+    }
+
+    /// The underlying offsets.
+    pub fn offsets(&self) -> &[u8] {
+        &self.offsets
+    }
+
+    /// Consumes the index, returning the underlying offsets.
+    pub fn into_offsets(self) -> Vec<u8> {
+        self.offsets
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> usize {
+        self.prefix_sum_at(self.offsets.len())
+    }
+
+    /// Sets the offset at `index` to `delta`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, delta: u8) {
+        let previous = std::mem::replace(&mut self.offsets[index], delta);
+        if delta >= previous {
+            self.update(index, |sum| sum + (delta - previous) as usize);
+        } else {
+            self.update(index, |sum| sum - (previous - delta) as usize);
+        }
+    }
+
+    /// Adds `diff` to the offset at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or the resulting offset does not
+    /// fit into a `u8`.
+    pub fn add(&mut self, index: usize, diff: i16) {
+        let offset = i16::from(self.offsets[index])
+            .checked_add(diff)
+            .and_then(|offset| u8::try_from(offset).ok())
+            .expect("the offset has to fit into a `u8`");
+        self.set(index, offset);
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, mut index: usize) -> usize {
+        assert!(index <= self.offsets.len());
+        let mut sum = 0;
+        while index > 0 {
+            sum += self.tree[index];
+            index -= lowest_bit(index);
+        }
+        sum
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as calling
+    /// [`prefix_sum_index`](crate::prefix_sum_index) with all of the
+    /// [`offsets`](Self::offsets), by descending the tree.
+    pub fn prefix_sum_index(&self, lookup: usize) -> Result<(usize, usize), usize> {
+        let len = self.offsets.len();
+        // `index` is the number of entries with a prefix sum `<= lookup`, and
+        // `remaining` is `lookup` minus their sum.
+        let mut index = 0;
+        let mut remaining = lookup;
+        let mut step = match len {
+            0 => 0,
+            _ => 1 << len.ilog2(),
+        };
+        while step > 0 {
+            let next = index + step;
+            if next <= len && self.tree[next] <= remaining {
+                index = next;
+                remaining -= self.tree[next];
+            }
+            step >>= 1;
+        }
+
+        let start = lookup - remaining;
+        match self.offsets.get(index) {
+            Some(offset) => Ok((index, start + *offset as usize)),
+            None => Err(start),
+        }
+    }
+
+    /// Applies `f` to all the tree nodes covering the offset at `index`.
+    fn update(&mut self, index: usize, f: impl Fn(usize) -> usize) {
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] = f(self.tree[i]);
+            i += lowest_bit(i);
+        }
+    }
+}
+
+impl From<Vec<u8>> for FenwickIndex {
+    fn from(offsets: Vec<u8>) -> Self {
+        Self::new(offsets)
+    }
+}
+
+/// The lowest set bit of `i`, which is the number of offsets `tree[i]` covers.
+#[inline]
+fn lowest_bit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+#[cfg(test)]
+use crate::prefix_sum_fallback;
+
+#[test]
+fn test_fenwick() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let index = FenwickIndex::default();
+    assert_eq!(index.prefix_sum_index(0), Err(0));
+    assert_eq!(index.prefix_sum_at(0), 0);
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [1, 2, 3, 7, 8, 9, 100, 1_000] {
+        let mut offsets: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let mut index = FenwickIndex::new(offsets.clone());
+
+        for _ in 0..20 {
+            for _ in 0..10 {
+                let i = rng.gen_range(0..len);
+                if rng.gen() {
+                    offsets[i] = rng.gen_range(0..4);
+                    index.set(i, offsets[i]);
+                } else {
+                    let diff = rng.gen_range(-(offsets[i] as i16)..4);
+                    offsets[i] = (offsets[i] as i16 + diff) as u8;
+                    index.add(i, diff);
+                }
+            }
+            assert_eq!(index.offsets(), &offsets[..]);
+
+            let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+            assert_eq!(index.total(), total);
+            for lookup in 0..total + 2 {
+                assert_eq!(
+                    index.prefix_sum_index(lookup),
+                    prefix_sum_fallback(&offsets, lookup)
+                );
+            }
+            for at in 0..=len {
+                assert_eq!(index.prefix_sum_at(at), crate::prefix_sum_at(&offsets, at));
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "the offset has to fit into a `u8`")]
+fn test_fenwick_add_overflow() {
+    let mut index = FenwickIndex::new(vec![1; 10]);
+    index.add(3, i16::MAX);
+}
@@ -0,0 +1,386 @@
+use crate::{prefix_sum_index, range_sum};
+
+/// The default number of offsets per block of a [`PrefixSumIndex`].
+const DEFAULT_BLOCK_LEN: usize = 256;
+
+/// How a [`PrefixSumIndex`] samples the prefix sums of its offsets.
+///
+/// | Layout       | Bytes per entry                               | Lookup                                   |
+/// |--------------|-----------------------------------------------|------------------------------------------|
+/// | [`Flat`]     | `1 + 8 / block_len`                           | `O(log(n / block_len) + block_len)`      |
+/// | [`TwoLevel`] | `1 + 8 / superblock_len + 2 / block_len`      | `O(log(n / superblock_len) + superblock_len / block_len + block_len)` |
+///
+/// The two-level layout gets away with small blocks, and thus short SIMD
+/// scans, without paying for an absolute sum per block.
+///
+/// [`Flat`]: Layout::Flat
+/// [`TwoLevel`]: Layout::TwoLevel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Layout {
+    /// The absolute prefix sum at the end of every block of `block_len`
+    /// offsets.
+    Flat {
+        /// The number of offsets per block, a non-zero multiple of 16.
+        block_len: usize,
+    },
+    /// The absolute prefix sum as `u64` at the end of every superblock of
+    /// `superblock_len` offsets, and the `u16` prefix sum relative to the
+    /// start of its superblock at the end of every block of `block_len`
+    /// offsets, like rank/select structures do.
+    TwoLevel {
+        /// The number of offsets per block, a non-zero multiple of 16.
+        block_len: usize,
+        /// The number of offsets per superblock, a multiple of `block_len`
+        /// of at most 256, so the relative sums fit into a `u16`.
+        superblock_len: usize,
+    },
+}
+
+impl Layout {
+    /// A two-level layout with blocks of 64 offsets in superblocks of 256,
+    /// which adds 1/16 of a byte per entry.
+    pub const TWO_LEVEL: Self = Self::TwoLevel {
+        block_len: 64,
+        superblock_len: 256,
+    };
+
+    /// The number of offsets per block.
+    pub fn block_len(self) -> usize {
+        match self {
+            Self::Flat { block_len } | Self::TwoLevel { block_len, .. } => block_len,
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::Flat {
+            block_len: DEFAULT_BLOCK_LEN,
+        }
+    }
+}
+
+/// The sampled prefix sums of a [`PrefixSumIndex`].
+#[derive(Clone, Debug)]
+enum Samples {
+    Flat {
+        /// The prefix sum at the end of each block.
+        block_ends: Vec<usize>,
+    },
+    TwoLevel {
+        blocks_per_superblock: usize,
+        /// The prefix sum at the end of each superblock.
+        superblock_ends: Vec<u64>,
+        /// The prefix sum at the end of each block, relative to the start of
+        /// its superblock.
+        block_ends: Vec<u16>,
+    },
+}
+
+/// An owned table of `u8` offsets, with sampled prefix sums for fast lookups.
+///
+/// Next to the offsets, this stores the prefix sum at the end of each block
+/// of `block_len` offsets. Lookups binary-search these samples, and then run
+/// [`prefix_sum_index`] on the single block that contains the `lookup`, which
+/// is `O(log(n / block_len) + block_len)` instead of `O(n)`.
+///
+/// With the default `block_len` of 256, the samples add 1/32 of a byte per
+/// entry on 64-bit targets. See [`Layout`] for a two-level layout that is
+/// more suitable for very large tables.
+///
+/// # Examples
+///
+/// ```
+/// use psy::{Layout, PrefixSumIndex};
+///
+/// let offsets = vec![0, 1, 0, 4, 8, 1, 2, 9, 8, 1];
+///
+/// let index = PrefixSumIndex::new(offsets.clone());
+/// assert_eq!(index.total(), 34);
+/// assert_eq!(index.prefix_sum_index(0), Ok((1, 1)));
+/// assert_eq!(index.prefix_sum_index(21), Ok((7, 25)));
+/// assert_eq!(index.prefix_sum_index(78), Err(34));
+///
+/// let index = PrefixSumIndex::with_layout(offsets, Layout::TWO_LEVEL);
+/// assert_eq!(index.prefix_sum_index(21), Ok((7, 25)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PrefixSumIndex {
+    offsets: Vec<u8>,
+    layout: Layout,
+    samples: Samples,
+    total: usize,
+}
+
+impl PrefixSumIndex {
+    /// Creates a new index using the default [`Layout`].
+    pub fn new(offsets: Vec<u8>) -> Self {
+        Self::with_layout(offsets, Layout::default())
+    }
+
+    /// Creates a new index with `block_len` offsets per sampled block.
+    ///
+    /// Larger blocks use less memory, smaller blocks make lookups scan less.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_len` is not a non-zero multiple of 16.
+    pub fn with_block_len(offsets: Vec<u8>, block_len: usize) -> Self {
+        Self::with_layout(offsets, Layout::Flat { block_len })
+    }
+
+    /// Creates a new index with the given [`Layout`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `block_len` is not a non-zero multiple of 16, or the
+    /// `superblock_len` of a [`Layout::TwoLevel`] is not a non-zero multiple of
+    /// the `block_len` of at most 256.
+    pub fn with_layout(offsets: Vec<u8>, layout: Layout) -> Self {
+        let block_len = layout.block_len();
+        assert!(
+            block_len > 0 && block_len & 15 == 0,
+            "`block_len` has to be a non-zero multiple of 16"
+        );
+
+        let mut total = 0;
+        let samples = match layout {
+            Layout::Flat { block_len } => Samples::Flat {
+                block_ends: offsets
+                    .chunks(block_len)
+                    .map(|block| {
+                        total += range_sum(block, 0, block.len());
+                        total
+                    })
+                    .collect(),
+            },
+            Layout::TwoLevel {
+                block_len,
+                superblock_len,
+            } => {
+                assert!(
+                    superblock_len > 0 && superblock_len <= 256 && superblock_len % block_len == 0,
+                    "`superblock_len` has to be a non-zero multiple of `block_len` of at most 256"
+                );
+
+                let mut block_ends = Vec::with_capacity(offsets.len().div_ceil(block_len));
+                let superblock_ends = offsets
+                    .chunks(superblock_len)
+                    .map(|superblock| {
+                        let mut sum = 0;
+                        for block in superblock.chunks(block_len) {
+                            sum += range_sum(block, 0, block.len());
+                            // at most 256 offsets of at most 255
+                            block_ends.push(sum as u16);
+                        }
+                        total += sum;
+                        total as u64
+                    })
+                    .collect();
+
+                Samples::TwoLevel {
+                    blocks_per_superblock: superblock_len / block_len,
+                    superblock_ends,
+                    block_ends,
+                }
+            }
+        };
+
+        Self {
+            offsets,
+            layout,
+            samples,
+            total,
+        }
+    }
+
+    /// The underlying offsets.
+    pub fn offsets(&self) -> &[u8] {
+        &self.offsets
+    }
+
+    /// Consumes the index, returning the underlying offsets.
+    pub fn into_offsets(self) -> Vec<u8> {
+        self.offsets
+    }
+
+    /// The [`Layout`] of the sampled prefix sums.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The number of offsets per sampled block.
+    pub fn block_len(&self) -> usize {
+        self.layout.block_len()
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as calling [`prefix_sum_index`] with
+    /// all of the [`offsets`](Self::offsets).
+    pub fn prefix_sum_index(&self, lookup: usize) -> Result<(usize, usize), usize> {
+        if lookup >= self.total {
+            return Err(self.total);
+        }
+
+        let block = match &self.samples {
+            Samples::Flat { block_ends } => block_ends.partition_point(|end| *end <= lookup),
+            Samples::TwoLevel {
+                blocks_per_superblock,
+                superblock_ends,
+                block_ends,
+            } => {
+                let superblock = superblock_ends.partition_point(|end| *end as usize <= lookup);
+                let first = superblock * blocks_per_superblock;
+                let blocks =
+                    &block_ends[first..block_ends.len().min(first + blocks_per_superblock)];
+                let relative = lookup - self.superblock_start(superblock);
+                first + blocks.partition_point(|end| *end as usize <= relative)
+            }
+        };
+
+        let start = self.block_start(block);
+        let from = block * self.block_len();
+        let to = self.offsets.len().min(from + self.block_len());
+        match prefix_sum_index(&self.offsets[from..to], lookup - start) {
+            Ok((index, sum)) => Ok((from + index, start + sum)),
+            // the end of the block is greater than `lookup`
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, index: usize) -> usize {
+        assert!(index <= self.offsets.len());
+        let block = index / self.block_len();
+        let from = block * self.block_len();
+        self.block_start(block) + range_sum(&self.offsets, from, index)
+    }
+
+    /// The prefix sum before the first entry of `block`.
+    fn block_start(&self, block: usize) -> usize {
+        match &self.samples {
+            Samples::Flat { block_ends } => match block {
+                0 => 0,
+                _ => block_ends[block - 1],
+            },
+            Samples::TwoLevel {
+                blocks_per_superblock,
+                block_ends,
+                ..
+            } => {
+                let superblock = block / blocks_per_superblock;
+                let start = self.superblock_start(superblock);
+                match block % blocks_per_superblock {
+                    0 => start,
+                    _ => start + block_ends[block - 1] as usize,
+                }
+            }
+        }
+    }
+
+    /// The prefix sum before the first entry of `superblock`.
+    fn superblock_start(&self, superblock: usize) -> usize {
+        match &self.samples {
+            Samples::TwoLevel {
+                superblock_ends, ..
+            } if superblock > 0 => superblock_ends[superblock - 1] as usize,
+            _ => 0,
+        }
+    }
+}
+
+impl Default for PrefixSumIndex {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl From<Vec<u8>> for PrefixSumIndex {
+    fn from(offsets: Vec<u8>) -> Self {
+        Self::new(offsets)
+    }
+}
+
+#[cfg(test)]
+use crate::{prefix_sum_at, prefix_sum_fallback};
+
+#[test]
+fn test_index() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let index = PrefixSumIndex::default();
+    assert_eq!(index.prefix_sum_index(0), Err(0));
+    assert_eq!(index.prefix_sum_at(0), 0);
+
+    // the relative sums of a full superblock still fit into a `u16`
+    let index = PrefixSumIndex::with_layout(vec![255; 1_000], Layout::TWO_LEVEL);
+    assert_eq!(index.prefix_sum_at(1_000), 255_000);
+    assert_eq!(index.prefix_sum_index(255 * 256), Ok((256, 255 * 257)));
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [1, 15, 16, 17, 255, 256, 257, 1_000, 5_000] {
+        let offsets: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+
+        let layouts = [
+            Layout::Flat { block_len: 16 },
+            Layout::Flat { block_len: 64 },
+            Layout::default(),
+            Layout::TwoLevel {
+                block_len: 16,
+                superblock_len: 256,
+            },
+            Layout::TwoLevel {
+                block_len: 32,
+                superblock_len: 96,
+            },
+            Layout::TWO_LEVEL,
+        ];
+        for layout in layouts {
+            let index = PrefixSumIndex::with_layout(offsets.clone(), layout);
+            assert_eq!(index.total(), total);
+
+            for lookup in 0..total + 2 {
+                assert_eq!(
+                    index.prefix_sum_index(lookup),
+                    prefix_sum_fallback(&offsets, lookup)
+                );
+            }
+            for at in (0..=len).step_by(7).chain([len]) {
+                assert_eq!(index.prefix_sum_at(at), prefix_sum_at(&offsets, at));
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "`superblock_len` has to be a non-zero multiple")]
+fn test_index_empty_superblock() {
+    let layout = Layout::TwoLevel {
+        block_len: 16,
+        superblock_len: 0,
+    };
+    PrefixSumIndex::with_layout(vec![1; 100], layout);
+}
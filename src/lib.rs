@@ -33,19 +33,130 @@
 //! assert_eq!(prefix_sum_index(&offsets, 78), Err(34));
 //! ```
 
+#[cfg(target_arch = "x86_64")]
 mod avx;
+#[cfg(target_arch = "x86_64")]
 mod avx2;
-#[cfg(test)]
+mod backend;
+mod batch;
+mod column;
+mod cursor;
+mod element;
+mod escape;
 mod fallback;
+mod fenwick;
+mod index;
+mod line;
+mod lookup;
+mod packed;
+mod rope;
+mod scan;
+mod search;
+#[cfg(target_arch = "x86_64")]
+mod sse2;
+mod sum;
+mod swar;
+mod varint;
 
-#[cfg(test)]
-use fallback::prefix_sum_fallback;
+pub use backend::{prefix_sum_index_with, Backend, UnsupportedBackend};
+pub use batch::prefix_sum_index_batch;
+pub use column::ColumnUnit;
+pub use cursor::Cursor;
+pub use element::OffsetElement;
+pub use escape::EscapedOffsets;
+pub use fallback::prefix_sum_fallback;
+pub use fenwick::FenwickIndex;
+pub use index::{Layout, PrefixSumIndex};
+pub use line::{LineCol, LineIndex};
+pub use lookup::{prefix_sum_lookup, Hit, OutOfBounds};
+pub use packed::PackedOffsets;
+pub use rope::OffsetRope;
+pub use scan::{exclusive_prefix_sums_into, prefix_sums_into, ScanOutput};
+pub use search::{prefix_sum_search, SearchMode};
+pub use sum::{prefix_sum_at, range_sum};
+pub use varint::{InvalidVarint, VarintOffsets};
 
 /// Calculate the Prefix Sum Index
 ///
+/// Use [`prefix_sum_search`] to find the first prefix sum that is **greater
+/// than or equal to** the `lookup` instead, or [`prefix_sum_lookup`] to get
+/// the start and end of the entry as well. Use [`prefix_sum_index_of`] for
+/// offsets wider than a `u8`.
+///
+/// The [`Backend`] is chosen at runtime, based on the features of the
+/// current CPU. Targets without a SIMD implementation use
+/// [`prefix_sum_fallback`], with identical results.
+///
 /// See [`crate level docs`](crate) for more information.
 pub fn prefix_sum_index(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
-    unsafe { avx2::prefix_sum_16(offsets, lookup) }
+    prefix_sum_index_of(offsets, lookup)
+}
+
+/// Calculate the Prefix Sum Index of any [`OffsetElement`] type.
+///
+/// This works exactly like [`prefix_sum_index`], but the `lookup` and the
+/// returned prefix sums are of the corresponding [`Sum`](OffsetElement::Sum)
+/// type.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sum_index_of;
+///
+/// assert_eq!(prefix_sum_index_of::<u64>(&[], 0), Err(0));
+/// assert_eq!(prefix_sum_index_of(&[80u32, 4_000_000_000], 80), Ok((1, 4_000_000_080)));
+/// ```
+pub fn prefix_sum_index_of<T: OffsetElement>(
+    offsets: &[T],
+    lookup: T::Sum,
+) -> Result<(usize, T::Sum), T::Sum> {
+    // SAFETY: `detect` only returns backends supported by the current CPU.
+    unsafe { T::prefix_sum_index_unchecked(Backend::detect(), offsets, lookup) }
+}
+
+/// Calculate the Prefix Sum Index of `u16` offsets.
+///
+/// This works exactly like [`prefix_sum_index`], but allows for offsets that
+/// do not fit into a `u8`. It is equivalent to calling
+/// [`prefix_sum_index_of`] with a `&[u16]`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sum_index_u16;
+///
+/// let offsets = [80, 300, 0, 1_000];
+///
+/// assert_eq!(prefix_sum_index_u16(&[], 0), Err(0));
+/// assert_eq!(prefix_sum_index_u16(&offsets, 0), Ok((0, 80)));
+/// assert_eq!(prefix_sum_index_u16(&offsets, 380), Ok((3, 1_380)));
+/// assert_eq!(prefix_sum_index_u16(&offsets, 2_000), Err(1_380));
+/// ```
+pub fn prefix_sum_index_u16(offsets: &[u16], lookup: usize) -> Result<(usize, usize), usize> {
+    prefix_sum_index_of(offsets, lookup)
+}
+
+/// Calculate the Prefix Sum Index of `u32` offsets.
+///
+/// This works exactly like [`prefix_sum_index`], but allows for offsets that
+/// do not fit into a `u16`. The prefix sums are calculated as `u64`, so they
+/// can exceed `u32::MAX` even on 32-bit targets. It is equivalent to calling
+/// [`prefix_sum_index_of`] with a `&[u32]`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sum_index_u32;
+///
+/// let offsets = [80, 4_000_000_000, 0, 1_000_000_000];
+///
+/// assert_eq!(prefix_sum_index_u32(&[], 0), Err(0));
+/// assert_eq!(prefix_sum_index_u32(&offsets, 0), Ok((0, 80)));
+/// assert_eq!(prefix_sum_index_u32(&offsets, 4_000_000_080), Ok((3, 5_000_000_080)));
+/// assert_eq!(prefix_sum_index_u32(&offsets, 6_000_000_000), Err(5_000_000_080));
+/// ```
+pub fn prefix_sum_index_u32(offsets: &[u32], lookup: u64) -> Result<(usize, u64), u64> {
+    prefix_sum_index_of(offsets, lookup)
 }
 
 #[test]
@@ -99,3 +210,96 @@ fn test_combined() {
         prefix_sum_fallback(&offsets, 35)
     );
 }
+
+#[test]
+fn test_backends() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 7, 8, 9, 15, 16, 17, 31, 32, 33, 100, 1_000] {
+        let offsets: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+
+        for lookup in (0..total + 2).step_by(7) {
+            let expected = prefix_sum_fallback(&offsets, lookup);
+            assert_eq!(prefix_sum_index(&offsets, lookup), expected);
+            for backend in Backend::ALL {
+                match prefix_sum_index_with(*backend, &offsets, lookup) {
+                    Ok(result) => assert_eq!(result, expected, "{}", backend),
+                    Err(err) => assert!(!backend.is_supported(), "{}", err),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backends_u16() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 7, 8, 9, 15, 16, 17, 100] {
+        let offsets: Vec<u16> = (0..len).map(|_| rng.gen()).collect();
+        let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+
+        for lookup in (0..total + 2).step_by(997) {
+            let expected = fallback::prefix_sum_scalar(&offsets, lookup);
+            assert_eq!(prefix_sum_index_u16(&offsets, lookup), expected);
+            for backend in Backend::available() {
+                let result = unsafe { backend.prefix_sum_index_u16_unchecked(&offsets, lookup) };
+                assert_eq!(result, expected, "{}", backend);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backends_u32() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 3, 4, 5, 7, 8, 9, 100] {
+        let offsets: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+        let total = offsets.iter().map(|o| *o as u64).sum::<u64>();
+
+        let mut lookups: Vec<u64> = (0..200).map(|_| rng.gen_range(0..total + 2)).collect();
+        lookups.extend([0, total.saturating_sub(1), total, u64::MAX]);
+        for lookup in lookups {
+            let expected = fallback::prefix_sum_scalar(&offsets, lookup);
+            assert_eq!(prefix_sum_index_u32(&offsets, lookup), expected);
+            for backend in Backend::available() {
+                let result = unsafe { backend.prefix_sum_index_u32_unchecked(&offsets, lookup) };
+                assert_eq!(result, expected, "{}", backend);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_elements() {
+    let offsets: [u8; 6] = [0, 1, 0, 4, 8, 1];
+    for lookup in [0, 1, 5, 13, 14, 20] {
+        let expected = prefix_sum_index(&offsets, lookup);
+        let widen = |result: Result<(usize, usize), usize>| {
+            result
+                .map(|(idx, sum)| (idx, sum as u64))
+                .map_err(|sum| sum as u64)
+        };
+
+        assert_eq!(
+            prefix_sum_index_of(&offsets.map(u16::from), lookup),
+            expected
+        );
+        assert_eq!(
+            prefix_sum_index_of(&offsets.map(u32::from), lookup as u64),
+            widen(expected)
+        );
+        assert_eq!(
+            prefix_sum_index_of(&offsets.map(u64::from), lookup as u64),
+            widen(expected)
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use crate::column::WideChars;
+use crate::{ColumnUnit, PrefixSumIndex};
+
+/// A zero-based line and column.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    /// The line number.
+    pub line: usize,
+    /// The number of bytes, or other [`ColumnUnit`]s, from the start of the
+    /// line.
+    pub col: usize,
+}
+
+/// Translates byte offsets in a text to lines and columns, and back.
+///
+/// Lines are terminated by either `\n`, `\r\n` or a lone `\r`. The length of
+/// each line, including its terminator, is stored as `u8` offsets in a
+/// [`PrefixSumIndex`]. Lines of 255 bytes or more are split up into one
+/// entry of `255` for each full 255 bytes, followed by the remaining bytes.
+/// Those continuation entries are rare, and tracked separately to map
+/// between entries and lines.
+///
+/// For columns in [`ColumnUnit`]s other than bytes, each line that has
+/// non-ASCII characters keeps a secondary table of their widths.
+///
+/// # Examples
+///
+/// ```
+/// use psy::{ColumnUnit, LineCol, LineIndex};
+///
+/// let index = LineIndex::new("fn main() {\r\n    println!();\n}\n");
+///
+/// assert_eq!(index.line_count(), 4);
+/// assert_eq!(index.line_col(17), Some(LineCol { line: 1, col: 4 }));
+/// assert_eq!(index.offset(2, 0), Some(29));
+/// assert_eq!(index.line_col(31), Some(LineCol { line: 3, col: 0 }));
+/// assert_eq!(index.line_col(32), None);
+///
+/// let index = LineIndex::new("let s = \"😀\";\nlet ä = s;");
+///
+/// assert_eq!(index.line_col_in(13, ColumnUnit::Utf16), Some(LineCol { line: 0, col: 11 }));
+/// assert_eq!(index.line_col_in(13, ColumnUnit::CodePoint), Some(LineCol { line: 0, col: 10 }));
+/// assert_eq!(index.offset_in(1, 6, ColumnUnit::Utf16), Some(23));
+/// assert_eq!(index.convert_col(1, 7, ColumnUnit::Utf8, ColumnUnit::CodePoint), Some(6));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    entries: PrefixSumIndex,
+    /// The indices of all the continuation entries.
+    continuations: Vec<usize>,
+    /// The lines with non-ASCII characters, sorted by line.
+    wide_lines: Vec<(usize, WideChars)>,
+}
+
+impl LineIndex {
+    /// Creates a new index of the lines in `text`.
+    pub fn new(text: &str) -> Self {
+        Self::from_bytes(text.as_bytes())
+    }
+
+    /// Creates a new index of the lines in `text`, which does not have to be
+    /// valid UTF-8.
+    ///
+    /// Columns in [`ColumnUnit`]s other than bytes are unspecified for lines
+    /// that are not valid UTF-8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a line has a run of more than `u32::MAX` ASCII characters
+    /// next to non-ASCII ones.
+    pub fn from_bytes(text: &[u8]) -> Self {
+        let mut entries = Vec::with_capacity(text.len() / 32);
+        let mut continuations = vec![];
+        let mut wide_lines = vec![];
+        let mut push_line = |line: &[u8]| {
+            if let Some(wide) = WideChars::new(line) {
+                wide_lines.push((entries.len() - continuations.len(), wide));
+            }
+
+            let mut len = line.len();
+            while len >= u8::MAX as usize {
+                continuations.push(entries.len());
+                entries.push(u8::MAX);
+                len -= u8::MAX as usize;
+            }
+            entries.push(len as u8);
+        };
+
+        let mut start = 0;
+        for (i, byte) in text.iter().enumerate() {
+            let is_end = match byte {
+                b'\n' => true,
+                b'\r' => text.get(i + 1) != Some(&b'\n'),
+                _ => false,
+            };
+            if is_end {
+                push_line(&text[start..=i]);
+                start = i + 1;
+            }
+        }
+        // the last line, which has no terminator, and might be empty
+        push_line(&text[start..]);
+
+        Self {
+            entries: PrefixSumIndex::new(entries),
+            continuations,
+            wide_lines,
+        }
+    }
+
+    /// The length of the text in bytes.
+    pub fn len(&self) -> usize {
+        self.entries.total()
+    }
+
+    /// Whether the text is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of lines, which is one more than the number of line
+    /// terminators.
+    pub fn line_count(&self) -> usize {
+        self.entries.len() - self.continuations.len()
+    }
+
+    /// The offset at which `line` starts, or `None` if there is no such line.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        if line >= self.line_count() {
+            return None;
+        }
+        Some(self.entries.prefix_sum_at(self.first_entry(line)))
+    }
+
+    /// Translates the byte `offset` to a [`LineCol`].
+    ///
+    /// Returns `None` if `offset` is past the end of the text. The end of the
+    /// text itself is on the last line.
+    pub fn line_col(&self, offset: usize) -> Option<LineCol> {
+        let entry = match self.entries.prefix_sum_index(offset) {
+            Ok((entry, _)) => entry,
+            Err(total) if offset == total => self.entries.len() - 1,
+            Err(_) => return None,
+        };
+        let line = entry - self.continuations.partition_point(|c| *c < entry);
+        let start = self.entries.prefix_sum_at(self.first_entry(line));
+        Some(LineCol {
+            line,
+            col: offset - start,
+        })
+    }
+
+    /// Translates a `line` and byte `col` to an offset.
+    ///
+    /// Returns `None` if there is no such line, or `col` is past the end of
+    /// it. A `col` pointing at the line terminator is valid.
+    pub fn offset(&self, line: usize, col: usize) -> Option<usize> {
+        let start = self.line_start(line)?;
+        let end = self.line_start(line + 1);
+        let offset = start.checked_add(col)?;
+        match end {
+            Some(end) if offset < end => Some(offset),
+            None if offset <= self.len() => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// Translates the byte `offset` to a [`LineCol`], with the column counted
+    /// in `unit`s.
+    ///
+    /// Returns `None` if `offset` is past the end of the text, or points into
+    /// the middle of a character.
+    pub fn line_col_in(&self, offset: usize, unit: ColumnUnit) -> Option<LineCol> {
+        let LineCol { line, col } = self.line_col(offset)?;
+        let col = self.convert_within(line, col, ColumnUnit::Utf8, unit)?;
+        Some(LineCol { line, col })
+    }
+
+    /// Translates a `line` and `col`, counted in `unit`s, to a byte offset.
+    ///
+    /// Returns `None` if there is no such line, `col` is past the end of it,
+    /// or points into the middle of a character.
+    pub fn offset_in(&self, line: usize, col: usize, unit: ColumnUnit) -> Option<usize> {
+        let col = self.convert_within(line, col, unit, ColumnUnit::Utf8)?;
+        self.offset(line, col)
+    }
+
+    /// Converts a `col` on `line` from one unit to another.
+    ///
+    /// Returns `None` if there is no such line, `col` is past the end of it,
+    /// or points into the middle of a character.
+    pub fn convert_col(
+        &self,
+        line: usize,
+        col: usize,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) -> Option<usize> {
+        let offset = self.offset_in(line, col, from)?;
+        Some(self.line_col_in(offset, to)?.col)
+    }
+
+    /// Converts a `col` on `line`, without checking it against the length of
+    /// the line.
+    fn convert_within(
+        &self,
+        line: usize,
+        col: usize,
+        from: ColumnUnit,
+        to: ColumnUnit,
+    ) -> Option<usize> {
+        match self
+            .wide_lines
+            .binary_search_by_key(&line, |(line, _)| *line)
+        {
+            Ok(idx) => self.wide_lines[idx].1.convert(col, from, to),
+            // all the units are the same for ASCII
+            Err(_) => Some(col),
+        }
+    }
+
+    /// The index of the first entry of `line`.
+    fn first_entry(&self, line: usize) -> usize {
+        // the `j`th continuation is on line `continuations[j] - j`, which is
+        // sorted, so binary search for the continuations on earlier lines.
+        let (mut lo, mut hi) = (0, self.continuations.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.continuations[mid] - mid < line {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        line + lo
+    }
+}
+
+#[cfg(test)]
+fn naive_line_starts(text: &[u8]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for (i, byte) in text.iter().enumerate() {
+        if *byte == b'\n' || (*byte == b'\r' && text.get(i + 1) != Some(&b'\n')) {
+            starts.push(i + 1);
+        }
+    }
+    starts
+}
+
+#[test]
+fn test_line_index() {
+    let long = "x".repeat(600);
+    let texts = [
+        String::new(),
+        "\n".into(),
+        "\r\n".into(),
+        "\r".into(),
+        "\n\r\r\n\n".into(),
+        "abc".into(),
+        "abc\n".into(),
+        "abc\r\ndef\rghi\n\njkl".into(),
+        format!("{}\n", &long[..254]),
+        format!("{}\n", &long[..255]),
+        format!("{}\r\n", &long[..254]),
+        format!("a\n{}\nb\n{}", &long[..509], &long[..510]),
+        format!("{}\r\n\r\n{long}", &long[..300]),
+    ];
+
+    for text in &texts {
+        let index = LineIndex::new(text);
+        let starts = naive_line_starts(text.as_bytes());
+        assert_eq!(index.len(), text.len());
+        assert_eq!(index.line_count(), starts.len());
+
+        for (line, start) in starts.iter().enumerate() {
+            assert_eq!(index.line_start(line), Some(*start));
+        }
+        assert_eq!(index.line_start(starts.len()), None);
+
+        for offset in 0..=text.len() {
+            let line = starts.partition_point(|start| *start <= offset) - 1;
+            let expected = LineCol {
+                line,
+                col: offset - starts[line],
+            };
+            assert_eq!(index.line_col(offset), Some(expected), "{:?}", text);
+            assert_eq!(index.offset(line, expected.col), Some(offset));
+        }
+        assert_eq!(index.line_col(text.len() + 1), None);
+
+        let last = starts.len() - 1;
+        assert_eq!(index.offset(last, text.len() - starts[last] + 1), None);
+        assert_eq!(index.offset(last + 1, 0), None);
+        if last > 0 {
+            assert_eq!(index.offset(0, starts[1]), None);
+        }
+    }
+}
+
+#[test]
+fn test_columns() {
+    let texts = ["äöü\n€\r\n😀 x 😀\ra̐éö̲\n", "ascii\nonly\n", ""];
+    let long = format!("{}€{}\n😀", "ä".repeat(200), "x".repeat(300));
+
+    for text in texts.into_iter().chain([long.as_str()]) {
+        let index = LineIndex::new(text);
+        let starts = naive_line_starts(text.as_bytes());
+        let units = [ColumnUnit::Utf8, ColumnUnit::Utf16, ColumnUnit::CodePoint];
+
+        for (line, start) in starts.iter().enumerate() {
+            let end = starts.get(line + 1).copied().unwrap_or(text.len() + 1);
+            let line_text = &text[*start..end.min(text.len())];
+
+            let mut cols = vec![];
+            let (mut utf16, mut code_points) = (0, 0);
+            for (utf8, c) in line_text.char_indices() {
+                cols.push([utf8, utf16, code_points]);
+                utf16 += c.len_utf16();
+                code_points += 1;
+            }
+            if end > text.len() {
+                // the end of the text is on the last line
+                cols.push([line_text.len(), utf16, code_points]);
+            }
+
+            for col in &cols {
+                for (from, from_col) in units.into_iter().zip(col) {
+                    let offset = start + col[0];
+                    let expected = LineCol {
+                        line,
+                        col: *from_col,
+                    };
+                    assert_eq!(index.line_col_in(offset, from), Some(expected));
+                    assert_eq!(index.offset_in(line, *from_col, from), Some(offset));
+                    for (to, to_col) in units.into_iter().zip(col) {
+                        assert_eq!(index.convert_col(line, *from_col, from, to), Some(*to_col));
+                    }
+                }
+            }
+
+            // past the end of the line
+            for (unit, col) in units.into_iter().zip(cols.last().unwrap()) {
+                assert_eq!(index.offset_in(line, col + 1, unit), None);
+            }
+        }
+
+        for offset in 0..text.len() {
+            let on_boundary = text.is_char_boundary(offset);
+            let utf16 = index.line_col_in(offset, ColumnUnit::Utf16);
+            assert_eq!(utf16.is_some(), on_boundary);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::{prefix_sum_index_of, OffsetElement};
+
+/// The entry that contains a looked up position.
+///
+/// `S` is the [`Sum`](OffsetElement::Sum) type of the offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Hit<S = usize> {
+    /// The index of the entry.
+    pub index: usize,
+    /// The prefix sum *before* the entry, which is where it starts.
+    pub start: S,
+    /// The prefix sum *including* the entry, which is where it ends.
+    pub end: S,
+    /// The position relative to the `start` of the entry.
+    pub offset_in_entry: S,
+}
+
+impl<S> Hit<S>
+where
+    S: Copy + core::ops::Sub<Output = S>,
+{
+    /// Creates a [`Hit`] from the `(index, prefix_sum)` returned by
+    /// [`prefix_sum_index_of`].
+    #[inline]
+    pub(crate) fn new<T>(offsets: &[T], lookup: S, (index, end): (usize, S)) -> Self
+    where
+        T: OffsetElement<Sum = S>,
+    {
+        let start = end - offsets[index].widen();
+        Self {
+            index,
+            start,
+            end,
+            offset_in_entry: lookup - start,
+        }
+    }
+}
+
+/// The error returned when a looked up position is outside of all the entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutOfBounds<S = usize> {
+    /// The sum of all the offsets.
+    pub total: S,
+}
+
+impl<S: fmt::Display> fmt::Display for OutOfBounds<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lookup is out of bounds, the offsets only sum up to {}",
+            self.total
+        )
+    }
+}
+
+impl<S: fmt::Debug + fmt::Display> std::error::Error for OutOfBounds<S> {}
+
+/// Look up the entry that contains the `lookup` position.
+///
+/// This does the same search as [`prefix_sum_index_of`], but returns the
+/// start and end of the entry, and the position of `lookup` within it.
+///
+/// # Examples
+///
+/// ```
+/// use psy::{prefix_sum_lookup, Hit, OutOfBounds};
+///
+/// // the lengths of some lines
+/// let offsets: [u8; 4] = [12, 0, 30, 7];
+///
+/// assert_eq!(
+///     prefix_sum_lookup(&offsets, 20),
+///     Ok(Hit { index: 2, start: 12, end: 42, offset_in_entry: 8 })
+/// );
+/// assert_eq!(prefix_sum_lookup(&offsets, 49), Err(OutOfBounds { total: 49 }));
+/// ```
+pub fn prefix_sum_lookup<T: OffsetElement>(
+    offsets: &[T],
+    lookup: T::Sum,
+) -> Result<Hit<T::Sum>, OutOfBounds<T::Sum>> {
+    match prefix_sum_index_of(offsets, lookup) {
+        Ok(found) => Ok(Hit::new(offsets, lookup, found)),
+        Err(total) => Err(OutOfBounds { total }),
+    }
+}
+
+#[test]
+fn test_lookup() {
+    let offsets: [u8; 6] = [0, 1, 0, 4, 8, 1];
+    assert_eq!(
+        prefix_sum_lookup(&offsets, 0),
+        Ok(Hit {
+            index: 1,
+            start: 0,
+            end: 1,
+            offset_in_entry: 0
+        })
+    );
+    assert_eq!(
+        prefix_sum_lookup(&offsets, 12),
+        Ok(Hit {
+            index: 4,
+            start: 5,
+            end: 13,
+            offset_in_entry: 7
+        })
+    );
+    assert_eq!(
+        prefix_sum_lookup(&offsets, 14),
+        Err(OutOfBounds { total: 14 })
+    );
+    assert_eq!(
+        prefix_sum_lookup::<u8>(&[], 0),
+        Err(OutOfBounds { total: 0 })
+    );
+
+    let offsets: [u32; 3] = [u32::MAX, 0, 5];
+    assert_eq!(
+        prefix_sum_lookup(&offsets, u32::MAX as u64 + 1),
+        Ok(Hit {
+            index: 2,
+            start: u32::MAX as u64,
+            end: u32::MAX as u64 + 5,
+            offset_in_entry: 1
+        })
+    );
+
+    let err = OutOfBounds { total: 14 };
+    assert_eq!(
+        err.to_string(),
+        "lookup is out of bounds, the offsets only sum up to 14"
+    );
+}
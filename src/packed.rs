@@ -0,0 +1,307 @@
+use crate::{prefix_sum_index, range_sum};
+
+/// The number of offsets per block, a multiple of both 8 and 16.
+const BLOCK_LEN: usize = 128;
+
+/// The number of blocks per superblock.
+///
+/// A superblock packs into at most `256 * 128` bytes, so the positions
+/// relative to it fit into a `u16`, and its offsets sum up to less than
+/// `u32::MAX`.
+const SUPERBLOCK_LEN: usize = 256;
+
+/// A block of bit-packed offsets.
+///
+/// This takes 8 bytes per 128 offsets, plus a 16 byte [`Superblock`] per 256
+/// blocks, which adds up to about 0.06 bytes per offset on top of the packed
+/// offsets themselves.
+#[derive(Clone, Copy, Debug)]
+struct Block {
+    /// The prefix sum at the end of the block, relative to the superblock.
+    end: u32,
+    /// The byte position of the packed offsets, relative to the superblock.
+    position: u16,
+    /// The number of bits per offset.
+    width: u8,
+}
+
+/// The absolute position of a run of [`SUPERBLOCK_LEN`] blocks.
+#[derive(Clone, Copy, Debug)]
+struct Superblock {
+    /// The byte position of the packed offsets.
+    position: usize,
+    /// The prefix sum *before* the superblock.
+    start: usize,
+}
+
+/// A table of `u8` offsets, bit-packed with a separate bit width per block.
+///
+/// The offsets are split up into blocks of 128, and each block is packed
+/// with the minimal number of bits needed for its largest offset. Offsets
+/// that fit into 2 bits thus take a quarter of the memory of a `&[u8]`, and
+/// blocks of only zeros take no memory at all, apart from the per-block
+/// prefix sums.
+///
+/// Lookups binary-search the per-block sums, unpack the single block that
+/// contains the `lookup` into plain bytes, and use [`prefix_sum_index`] on
+/// those.
+///
+/// # Examples
+///
+/// ```
+/// use psy::PackedOffsets;
+///
+/// let offsets = PackedOffsets::new(&[0, 1, 0, 3, 2, 1, 2, 3]);
+///
+/// assert_eq!(offsets.packed_len(), 2);
+/// assert_eq!(offsets.get(3), Some(3));
+/// assert_eq!(offsets.prefix_sum_index(0), Ok((1, 1)));
+/// assert_eq!(offsets.prefix_sum_index(4), Ok((4, 6)));
+/// assert_eq!(offsets.prefix_sum_index(12), Err(12));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PackedOffsets {
+    bytes: Vec<u8>,
+    blocks: Vec<Block>,
+    superblocks: Vec<Superblock>,
+    len: usize,
+    total: usize,
+}
+
+impl PackedOffsets {
+    /// Packs the `offsets`.
+    pub fn new(offsets: &[u8]) -> Self {
+        let mut bytes = vec![];
+        let mut superblocks = vec![];
+        let mut total = 0;
+        let blocks = offsets
+            .chunks(BLOCK_LEN)
+            .enumerate()
+            .map(|(index, block)| {
+                if index & (SUPERBLOCK_LEN - 1) == 0 {
+                    superblocks.push(Superblock {
+                        position: bytes.len(),
+                        start: total,
+                    });
+                }
+                let superblock = superblocks.last().unwrap();
+                let max = block.iter().copied().max().unwrap_or_default();
+                let width = (u8::BITS - max.leading_zeros()) as u8;
+                let position = (bytes.len() - superblock.position) as u16;
+
+                // each group of 8 offsets fills exactly `width` bytes
+                for group in block.chunks(8) {
+                    let word = group.iter().enumerate().fold(0u64, |word, (i, offset)| {
+                        word | (*offset as u64) << (i * width as usize)
+                    });
+                    bytes.extend_from_slice(&word.to_le_bytes()[..width as usize]);
+                }
+
+                total += range_sum(block, 0, block.len());
+                Block {
+                    end: (total - superblock.start) as u32,
+                    position,
+                    width,
+                }
+            })
+            .collect();
+
+        Self {
+            bytes,
+            blocks,
+            superblocks,
+            len: offsets.len(),
+            total,
+        }
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bytes the packed offsets take up, not counting the
+    /// per-block metadata.
+    pub fn packed_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the offset at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<u8> {
+        if index >= self.len {
+            return None;
+        }
+        let mut buf = [0; BLOCK_LEN];
+        let offsets = self.unpack(index / BLOCK_LEN, &mut buf);
+        Some(offsets[index % BLOCK_LEN])
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, index: usize) -> usize {
+        assert!(index <= self.len);
+        let block = index / BLOCK_LEN;
+        if block == self.blocks.len() {
+            return self.total();
+        }
+        let mut buf = [0; BLOCK_LEN];
+        let offsets = self.unpack(block, &mut buf);
+        self.block_start(block) + range_sum(offsets, 0, index % BLOCK_LEN)
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as [`prefix_sum_index`] would for the
+    /// unpacked offsets.
+    pub fn prefix_sum_index(&self, lookup: usize) -> Result<(usize, usize), usize> {
+        if lookup >= self.total {
+            return Err(self.total);
+        }
+
+        // the last superblock that starts at or before `lookup`, which thus
+        // also ends after it.
+        let superblock = self.superblocks.partition_point(|s| s.start <= lookup) - 1;
+        let first = superblock * SUPERBLOCK_LEN;
+        let blocks = &self.blocks[first..self.blocks.len().min(first + SUPERBLOCK_LEN)];
+        let relative = lookup - self.superblocks[superblock].start;
+        let block = first + blocks.partition_point(|block| block.end as usize <= relative);
+
+        let mut buf = [0; BLOCK_LEN];
+        let offsets = self.unpack(block, &mut buf);
+        let start = self.block_start(block);
+        match prefix_sum_index(offsets, lookup - start) {
+            Ok((index, sum)) => Ok((block * BLOCK_LEN + index, start + sum)),
+            // the end of the block is greater than `lookup`
+            Err(_) => unreachable!(),
+        }
+    }
+
+    /// The prefix sum before the first entry of `block`.
+    fn block_start(&self, block: usize) -> usize {
+        let start = self.superblocks[block / SUPERBLOCK_LEN].start;
+        match block % SUPERBLOCK_LEN {
+            0 => start,
+            _ => start + self.blocks[block - 1].end as usize,
+        }
+    }
+
+    /// Unpacks the offsets of `block` into `buf`, returning them.
+    fn unpack<'b>(&self, block: usize, buf: &'b mut [u8; BLOCK_LEN]) -> &'b [u8] {
+        let Block {
+            position, width, ..
+        } = self.blocks[block];
+        let position = self.superblocks[block / SUPERBLOCK_LEN].position + position as usize;
+        let len = BLOCK_LEN.min(self.len - block * BLOCK_LEN);
+
+        let width = width as usize;
+        if width > 0 {
+            let mask = (1u64 << width) - 1;
+            let packed = &self.bytes[position..position + len.div_ceil(8) * width];
+            for (group, out) in packed.chunks_exact(width).zip(buf.chunks_exact_mut(8)) {
+                let mut word = [0; 8];
+                word[..width].copy_from_slice(group);
+                let word = u64::from_le_bytes(word);
+                for (i, out) in out.iter_mut().enumerate() {
+                    *out = ((word >> (i * width)) & mask) as u8;
+                }
+            }
+        }
+        &buf[..len]
+    }
+}
+
+impl From<&[u8]> for PackedOffsets {
+    fn from(offsets: &[u8]) -> Self {
+        Self::new(offsets)
+    }
+}
+
+#[cfg(test)]
+use crate::prefix_sum_fallback;
+
+#[test]
+fn test_packed() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let offsets = PackedOffsets::default();
+    assert_eq!(offsets.prefix_sum_index(0), Err(0));
+    assert_eq!(offsets.prefix_sum_at(0), 0);
+    assert_eq!(offsets.get(0), None);
+
+    // two bits per offset, and nothing for the zeros
+    let mut values = vec![3; 1_000];
+    values[256..384].fill(0);
+    assert_eq!(PackedOffsets::new(&values).packed_len(), (1_000 - 128) / 4);
+
+    // 8 bytes of metadata per block
+    assert_eq!(std::mem::size_of::<Block>(), 8);
+
+    // several superblocks, with the largest possible relative sums and an
+    // empty superblock in between
+    let superblock = SUPERBLOCK_LEN * BLOCK_LEN;
+    let mut values = vec![255; 3 * superblock + 100];
+    values[superblock..2 * superblock].fill(0);
+    let offsets = PackedOffsets::new(&values);
+    assert_eq!(offsets.total(), 255 * (2 * superblock + 100));
+    for index in (0..=values.len())
+        .step_by(997)
+        .chain([superblock, 2 * superblock])
+    {
+        assert_eq!(
+            offsets.prefix_sum_at(index),
+            crate::prefix_sum_at(&values, index)
+        );
+        if let Some(value) = values.get(index) {
+            assert_eq!(offsets.get(index), Some(*value));
+        }
+    }
+    for lookup in (0..offsets.total() + 2)
+        .step_by(99_991)
+        .chain([255 * superblock - 1, 255 * superblock])
+    {
+        assert_eq!(
+            offsets.prefix_sum_index(lookup),
+            prefix_sum_fallback(&values, lookup)
+        );
+    }
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [1, 7, 8, 9, 127, 128, 129, 1_000] {
+        for max in [0, 1, 3, 15, 100, 255] {
+            let values: Vec<u8> = (0..len).map(|_| rng.gen_range(0..=max)).collect();
+            let offsets = PackedOffsets::new(&values);
+            let total = values.iter().map(|v| *v as usize).sum::<usize>();
+            assert_eq!(offsets.total(), total);
+
+            for (index, value) in values.iter().enumerate() {
+                assert_eq!(offsets.get(index), Some(*value));
+            }
+            for lookup in (0..total + 2).step_by(total / 300 + 1).chain([total]) {
+                assert_eq!(
+                    offsets.prefix_sum_index(lookup),
+                    prefix_sum_fallback(&values, lookup)
+                );
+            }
+            for index in 0..=len {
+                assert_eq!(
+                    offsets.prefix_sum_at(index),
+                    crate::prefix_sum_at(&values, index)
+                );
+            }
+        }
+    }
+}
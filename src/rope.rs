@@ -0,0 +1,487 @@
+use core::ops::{Bound, RangeBounds};
+
+use crate::{prefix_sum_index, range_sum};
+
+/// The maximum number of offsets in a leaf.
+const MAX_LEAF: usize = 128;
+/// The maximum number of children of an internal node.
+const MAX_CHILDREN: usize = 16;
+
+/// A sequence of `u8` offsets that supports inserting and removing entries.
+///
+/// This is a B-tree, whose leaves are small chunks of offsets searched with
+/// the SIMD kernels of [`prefix_sum_index`]. The internal nodes cache the
+/// number of offsets and their sum for each subtree, so lookups, inserts and
+/// removals all take `O(log n)`.
+///
+/// Use this when entries are inserted or removed, like lines in a text
+/// editor, and [`FenwickIndex`](crate::FenwickIndex) when entries are only
+/// ever updated in place.
+///
+/// # Examples
+///
+/// ```
+/// use psy::OffsetRope;
+///
+/// let mut rope = OffsetRope::from(vec![0, 1, 0, 4, 8, 1]);
+/// assert_eq!(rope.prefix_sum_index(5), Ok((4, 13)));
+///
+/// rope.insert(2, 10);
+/// assert_eq!(rope.prefix_sum_index(5), Ok((2, 11)));
+///
+/// assert_eq!(rope.remove(2), 10);
+/// rope.splice(0..2, [3, 3, 3]);
+/// assert_eq!(rope.to_vec(), [3, 3, 3, 0, 4, 8, 1]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OffsetRope {
+    root: Node,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    /// The number of offsets in this subtree.
+    len: usize,
+    /// The sum of the offsets in this subtree.
+    sum: usize,
+    children: Children,
+}
+
+#[derive(Clone, Debug)]
+enum Children {
+    Leaf(Vec<u8>),
+    Internal(Vec<Node>),
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node::leaf(Vec::new())
+    }
+}
+
+impl Node {
+    fn leaf(offsets: Vec<u8>) -> Self {
+        Self {
+            len: offsets.len(),
+            sum: range_sum(&offsets, 0, offsets.len()),
+            children: Children::Leaf(offsets),
+        }
+    }
+
+    fn internal(children: Vec<Node>) -> Self {
+        Self {
+            len: children.iter().map(|child| child.len).sum(),
+            sum: children.iter().map(|child| child.sum).sum(),
+            children: Children::Internal(children),
+        }
+    }
+
+    /// Whether this node should be merged with one of its siblings.
+    fn is_underfull(&self) -> bool {
+        match &self.children {
+            Children::Leaf(offsets) => offsets.len() < MAX_LEAF / 2,
+            Children::Internal(children) => children.len() < MAX_CHILDREN / 2,
+        }
+    }
+
+    /// Whether this node has to be split up.
+    fn is_overfull(&self) -> bool {
+        match &self.children {
+            Children::Leaf(offsets) => offsets.len() > MAX_LEAF,
+            Children::Internal(children) => children.len() > MAX_CHILDREN,
+        }
+    }
+
+    /// Moves the second half of this node into a new sibling.
+    fn split_off(&mut self) -> Node {
+        let sibling = match &mut self.children {
+            Children::Leaf(offsets) => Node::leaf(offsets.split_off(offsets.len() / 2)),
+            Children::Internal(children) => Node::internal(children.split_off(children.len() / 2)),
+        };
+        self.len -= sibling.len;
+        self.sum -= sibling.sum;
+        sibling
+    }
+
+    /// Moves all the contents of `next` to the end of this node.
+    fn append(&mut self, next: Node) {
+        self.len += next.len;
+        self.sum += next.sum;
+        match (&mut self.children, next.children) {
+            (Children::Leaf(offsets), Children::Leaf(next)) => offsets.extend(next),
+            (Children::Internal(children), Children::Internal(next)) => children.extend(next),
+            // all the leaves are at the same depth
+            _ => unreachable!(),
+        }
+    }
+
+    /// Finds the child containing `index`, returning its position and the
+    /// number of offsets before it.
+    ///
+    /// An `index` at the end of a child is found in that child, so the
+    /// index at the very end can be inserted into the last child.
+    fn find_child(children: &[Node], mut index: usize) -> (usize, usize) {
+        let mut before = 0;
+        for (k, child) in children.iter().enumerate() {
+            if index <= child.len && (index < child.len || k + 1 == children.len()) {
+                return (k, before);
+            }
+            index -= child.len;
+            before += child.len;
+        }
+        unreachable!()
+    }
+
+    /// Inserts `delta` at `index`, returning a new sibling if this node had
+    /// to be split up.
+    fn insert(&mut self, index: usize, delta: u8) -> Option<Node> {
+        self.len += 1;
+        self.sum += delta as usize;
+        match &mut self.children {
+            Children::Leaf(offsets) => offsets.insert(index, delta),
+            Children::Internal(children) => {
+                let (k, before) = Self::find_child(children, index);
+                if let Some(sibling) = children[k].insert(index - before, delta) {
+                    children.insert(k + 1, sibling);
+                }
+            }
+        }
+        self.is_overfull().then(|| self.split_off())
+    }
+
+    /// Removes the offset at `index`.
+    fn remove(&mut self, index: usize) -> u8 {
+        let delta = match &mut self.children {
+            Children::Leaf(offsets) => offsets.remove(index),
+            Children::Internal(children) => {
+                let (k, before) = Self::find_child(children, index);
+                let delta = children[k].remove(index - before);
+                if children[k].is_underfull() && children.len() > 1 {
+                    Self::rebalance(children, k);
+                }
+                delta
+            }
+        };
+        self.len -= 1;
+        self.sum -= delta as usize;
+        delta
+    }
+
+    /// Merges the underfull child at `k` with one of its siblings, splitting
+    /// them up evenly again if they do not fit into a single node.
+    fn rebalance(children: &mut Vec<Node>, k: usize) {
+        let k = k.min(children.len() - 2);
+        let next = children.remove(k + 1);
+        let merged = &mut children[k];
+        merged.append(next);
+        if merged.is_overfull() {
+            let sibling = merged.split_off();
+            children.insert(k + 1, sibling);
+        }
+    }
+}
+
+impl OffsetRope {
+    /// Creates an empty rope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.root.len
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.root.len == 0
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> usize {
+        self.root.sum
+    }
+
+    /// Returns the offset at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, mut index: usize) -> Option<u8> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut node = &self.root;
+        loop {
+            match &node.children {
+                Children::Leaf(offsets) => return offsets.get(index).copied(),
+                Children::Internal(children) => {
+                    let (k, before) = Node::find_child(children, index);
+                    node = &children[k];
+                    index -= before;
+                }
+            }
+        }
+    }
+
+    /// Copies all the offsets into a `Vec`.
+    pub fn to_vec(&self) -> Vec<u8> {
+        fn collect(node: &Node, out: &mut Vec<u8>) {
+            match &node.children {
+                Children::Leaf(offsets) => out.extend_from_slice(offsets),
+                Children::Internal(children) => {
+                    children.iter().for_each(|child| collect(child, out))
+                }
+            }
+        }
+
+        let mut out = Vec::with_capacity(self.len());
+        collect(&self.root, &mut out);
+        out
+    }
+
+    /// Inserts `delta` at `index`, shifting all the following offsets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn insert(&mut self, index: usize, delta: u8) {
+        assert!(index <= self.len(), "insertion index is out of bounds");
+        if let Some(sibling) = self.root.insert(index, delta) {
+            let root = std::mem::take(&mut self.root);
+            self.root = Node::internal(vec![root, sibling]);
+        }
+    }
+
+    /// Removes and returns the offset at `index`, shifting all the following
+    /// offsets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> u8 {
+        assert!(index < self.len(), "removal index is out of bounds");
+        let delta = self.root.remove(index);
+        if let Children::Internal(children) = &mut self.root.children {
+            if children.len() == 1 {
+                self.root = children.pop().unwrap();
+            }
+        }
+        delta
+    }
+
+    /// Replaces the offsets in `range` with the ones in `replace_with`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `range` is out of bounds.
+    pub fn splice<R, I>(&mut self, range: R, replace_with: I)
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = u8>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end + 1,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(start <= end && end <= self.len(), "range is out of bounds");
+
+        for _ in start..end {
+            self.remove(start);
+        }
+        for (index, delta) in (start..).zip(replace_with) {
+            self.insert(index, delta);
+        }
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, mut index: usize) -> usize {
+        assert!(index <= self.len());
+        let mut node = &self.root;
+        let mut start = 0;
+        loop {
+            match &node.children {
+                Children::Leaf(offsets) => return start + range_sum(offsets, 0, index),
+                Children::Internal(children) => {
+                    let (k, before) = Node::find_child(children, index);
+                    start += children[..k].iter().map(|child| child.sum).sum::<usize>();
+                    node = &children[k];
+                    index -= before;
+                }
+            }
+        }
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as calling [`prefix_sum_index`] with all
+    /// of the offsets.
+    pub fn prefix_sum_index(&self, mut lookup: usize) -> Result<(usize, usize), usize> {
+        if lookup >= self.total() {
+            return Err(self.total());
+        }
+
+        let mut node = &self.root;
+        let mut index = 0;
+        let mut start = 0;
+        loop {
+            match &node.children {
+                Children::Leaf(offsets) => {
+                    return match prefix_sum_index(offsets, lookup) {
+                        Ok((idx, sum)) => Ok((index + idx, start + sum)),
+                        // the sum of the leaf is greater than `lookup`
+                        Err(_) => unreachable!(),
+                    };
+                }
+                Children::Internal(children) => {
+                    for child in children {
+                        if lookup < child.sum {
+                            node = child;
+                            break;
+                        }
+                        lookup -= child.sum;
+                        index += child.len;
+                        start += child.sum;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for OffsetRope {
+    fn from(offsets: Vec<u8>) -> Self {
+        offsets.into_iter().collect()
+    }
+}
+
+impl FromIterator<u8> for OffsetRope {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let offsets: Vec<u8> = iter.into_iter().collect();
+        if offsets.is_empty() {
+            return Self::default();
+        }
+
+        // build the tree bottom up, leaving room for inserts in every node
+        let mut nodes: Vec<Node> = offsets
+            .chunks(MAX_LEAF * 3 / 4)
+            .map(|chunk| Node::leaf(chunk.to_vec()))
+            .collect();
+        while nodes.len() > 1 {
+            // spread the nodes evenly, so no parent is left with a single child
+            let len = nodes.len();
+            let parents = len.div_ceil(MAX_CHILDREN * 3 / 4);
+            let mut nodes_iter = nodes.into_iter();
+            nodes = (0..parents)
+                .map(|i| Node::internal((&mut nodes_iter).take((len + i) / parents).collect()))
+                .collect();
+        }
+        Self {
+            root: nodes.pop().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+use crate::prefix_sum_fallback;
+
+/// Checks the cached sums and that all leaves are at the same depth,
+/// returning the depth.
+#[cfg(test)]
+fn check_node(node: &Node) -> usize {
+    match &node.children {
+        Children::Leaf(offsets) => {
+            assert_eq!(node.len, offsets.len());
+            assert_eq!(node.sum, offsets.iter().map(|o| *o as usize).sum::<usize>());
+            assert!(offsets.len() <= MAX_LEAF);
+            0
+        }
+        Children::Internal(children) => {
+            assert!(children.len() > 1 && children.len() <= MAX_CHILDREN);
+            assert_eq!(node.len, children.iter().map(|c| c.len).sum::<usize>());
+            assert_eq!(node.sum, children.iter().map(|c| c.sum).sum::<usize>());
+            let depths: Vec<_> = children.iter().map(check_node).collect();
+            assert!(depths.iter().all(|depth| *depth == depths[0]));
+            depths[0] + 1
+        }
+    }
+}
+
+#[test]
+fn test_rope() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let rope = OffsetRope::new();
+    assert_eq!(rope.prefix_sum_index(0), Err(0));
+    assert_eq!(rope.prefix_sum_at(0), 0);
+    assert_eq!(rope.get(0), None);
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 100, 1_000, 96 * 13, 10_000, 96 * 12 * 13] {
+        let mut offsets: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+        let mut rope = OffsetRope::from(offsets.clone());
+        check_node(&rope.root);
+
+        for round in 0..20 {
+            // grow during the first rounds, and shrink afterwards
+            let grow = round < 10;
+            for _ in 0..200 {
+                let delta = rng.gen_range(0..4);
+                match rng.gen_range(0..8) {
+                    0..=2 if grow || offsets.is_empty() => {
+                        let i = rng.gen_range(0..=offsets.len());
+                        offsets.insert(i, delta);
+                        rope.insert(i, delta);
+                    }
+                    0..=5 if !offsets.is_empty() => {
+                        let i = rng.gen_range(0..offsets.len());
+                        assert_eq!(rope.remove(i), offsets.remove(i));
+                    }
+                    _ => {
+                        let start = rng.gen_range(0..=offsets.len());
+                        let end = rng.gen_range(start..=offsets.len().min(start + 20));
+                        let count = if grow { 30 } else { 10 };
+                        let replace: Vec<u8> = (0..rng.gen_range(0..count))
+                            .map(|_| rng.gen_range(0..4))
+                            .collect();
+                        offsets.splice(start..end, replace.clone());
+                        rope.splice(start..end, replace);
+                    }
+                }
+            }
+
+            check_node(&rope.root);
+            assert_eq!(rope.to_vec(), offsets);
+            assert_eq!(rope.len(), offsets.len());
+            let total = offsets.iter().map(|o| *o as usize).sum::<usize>();
+            assert_eq!(rope.total(), total);
+
+            for lookup in (0..total + 2).step_by(total / 100 + 1).chain([total]) {
+                assert_eq!(
+                    rope.prefix_sum_index(lookup),
+                    prefix_sum_fallback(&offsets, lookup)
+                );
+            }
+            for at in (0..=offsets.len())
+                .step_by(offsets.len() / 100 + 1)
+                .chain([offsets.len()])
+            {
+                assert_eq!(rope.prefix_sum_at(at), crate::prefix_sum_at(&offsets, at));
+            }
+            for i in (0..offsets.len() + 2)
+                .step_by(11)
+                .chain([offsets.len() + 1])
+            {
+                assert_eq!(rope.get(i), offsets.get(i).copied());
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::Backend;
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// An integer type the prefix sums can be written out as.
+///
+/// The prefix sums wrap around if they do not fit into the output type.
+///
+/// This trait is sealed, and can not be implemented outside of this crate.
+pub trait ScanOutput: Copy + sealed::Sealed {
+    /// The size of the integer in bytes.
+    #[doc(hidden)]
+    const BYTES: usize;
+
+    /// Truncates the sum to this type.
+    #[doc(hidden)]
+    fn from_sum(sum: u64) -> Self;
+}
+
+impl ScanOutput for u16 {
+    const BYTES: usize = 2;
+
+    #[inline]
+    fn from_sum(sum: u64) -> Self {
+        sum as u16
+    }
+}
+
+impl ScanOutput for u32 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn from_sum(sum: u64) -> Self {
+        sum as u32
+    }
+}
+
+impl ScanOutput for u64 {
+    const BYTES: usize = 8;
+
+    #[inline]
+    fn from_sum(sum: u64) -> Self {
+        sum
+    }
+}
+
+/// Writes the inclusive prefix sums of `offsets` to `out`.
+///
+/// Each element of `out` is the sum of all the `offsets` up to and including
+/// the one at the same index, so this materializes the sums that
+/// [`prefix_sum_index`](crate::prefix_sum_index) computes on the fly.
+///
+/// # Panics
+///
+/// Panics if `out` does not have the same length as `offsets`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sums_into;
+///
+/// let offsets = [12, 0, 30, 7];
+/// let mut out = [0u32; 4];
+/// prefix_sums_into(&offsets, &mut out);
+///
+/// assert_eq!(out, [12, 12, 42, 49]);
+/// ```
+pub fn prefix_sums_into<O: ScanOutput>(offsets: &[u8], out: &mut [O]) {
+    assert_eq!(offsets.len(), out.len());
+    // SAFETY: `detect` only returns backends supported by the current CPU.
+    unsafe { Backend::detect().prefix_sums_unchecked::<O, false>(offsets, out) }
+}
+
+/// Writes the exclusive prefix sums of `offsets` to `out`.
+///
+/// Each element of `out` is the sum of all the `offsets` *before* the one at
+/// the same index, which is the start of that entry, just like
+/// [`prefix_sum_at`](crate::prefix_sum_at).
+///
+/// # Panics
+///
+/// Panics if `out` does not have the same length as `offsets`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::exclusive_prefix_sums_into;
+///
+/// let offsets = [12, 0, 30, 7];
+/// let mut out = [0u32; 4];
+/// exclusive_prefix_sums_into(&offsets, &mut out);
+///
+/// assert_eq!(out, [0, 12, 12, 42]);
+/// ```
+pub fn exclusive_prefix_sums_into<O: ScanOutput>(offsets: &[u8], out: &mut [O]) {
+    assert_eq!(offsets.len(), out.len());
+    // SAFETY: `detect` only returns backends supported by the current CPU.
+    unsafe { Backend::detect().prefix_sums_unchecked::<O, true>(offsets, out) }
+}
+
+/// Writes the prefix sums of `offsets` to `out`, starting at `carry`.
+pub(crate) fn prefix_sums_scalar<O: ScanOutput, const EXCLUSIVE: bool>(
+    offsets: &[u8],
+    out: &mut [O],
+    mut carry: u64,
+) {
+    for (offset, out) in offsets.iter().zip(out) {
+        let next = carry.wrapping_add(*offset as u64);
+        *out = O::from_sum(if EXCLUSIVE { carry } else { next });
+        carry = next;
+    }
+}
+
+#[test]
+fn test_scan() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    fn check<O: ScanOutput + Default + Eq + core::fmt::Debug>(offsets: &[u8]) {
+        let mut expected = vec![O::default(); offsets.len()];
+        let mut out = vec![O::default(); offsets.len()];
+
+        prefix_sums_scalar::<O, false>(offsets, &mut expected, 0);
+        prefix_sums_into(offsets, &mut out);
+        assert_eq!(out, expected);
+        for backend in Backend::available() {
+            unsafe { backend.prefix_sums_unchecked::<O, false>(offsets, &mut out) };
+            assert_eq!(out, expected, "{}", backend);
+        }
+
+        prefix_sums_scalar::<O, true>(offsets, &mut expected, 0);
+        exclusive_prefix_sums_into(offsets, &mut out);
+        assert_eq!(out, expected);
+        for backend in Backend::available() {
+            unsafe { backend.prefix_sums_unchecked::<O, true>(offsets, &mut out) };
+            assert_eq!(out, expected, "{}", backend);
+        }
+    }
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [0, 1, 15, 16, 17, 31, 32, 33, 100, 1_000] {
+        let offsets: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+        check::<u16>(&offsets);
+        check::<u32>(&offsets);
+        check::<u64>(&offsets);
+
+        let full = vec![255; len];
+        check::<u16>(&full);
+        check::<u32>(&full);
+        check::<u64>(&full);
+    }
+
+    // `u16` sums wrap around
+    let offsets = [255; 300];
+    let mut out = [0u16; 300];
+    prefix_sums_into(&offsets, &mut out);
+    assert_eq!(out[299], (255 * 300) as u16);
+    let mut out = [0u32; 300];
+    prefix_sums_into(&offsets, &mut out);
+    assert_eq!(out[299], 255 * 300);
+}
@@ -0,0 +1,107 @@
+use crate::{prefix_sum_index_of, OffsetElement};
+
+/// How [`prefix_sum_search`] compares the prefix sums with the `lookup`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SearchMode {
+    /// Find the first prefix sum **greater than** the `lookup`.
+    ///
+    /// This is the mode of [`prefix_sum_index`](crate::prefix_sum_index),
+    /// and finds the entry that *contains* the `lookup` position. Empty
+    /// entries are never returned.
+    #[default]
+    UpperBound,
+    /// Find the first prefix sum **greater than or equal to** the `lookup`.
+    ///
+    /// This finds the entry that *ends* at the `lookup` position, which can
+    /// also be an empty entry.
+    LowerBound,
+}
+
+/// Calculate the Prefix Sum Index using the given [`SearchMode`].
+///
+/// With [`SearchMode::UpperBound`], this is the same as
+/// [`prefix_sum_index_of`].
+/// With [`SearchMode::LowerBound`], this returns the first index whose prefix
+/// sum is **greater than or equal to** the `lookup`.
+///
+/// In both modes, `Err(prefix_sum)` is returned if there is no such index.
+///
+/// # Examples
+///
+/// ```
+/// use psy::{prefix_sum_search, SearchMode};
+///
+/// let offsets: [u8; 5] = [
+///     0, // 0
+///     2, // 2
+///     0, // 2
+///     3, // 5
+///     0, // 5
+/// ];
+///
+/// assert_eq!(prefix_sum_search(&offsets, 0, SearchMode::UpperBound), Ok((1, 2)));
+/// assert_eq!(prefix_sum_search(&offsets, 0, SearchMode::LowerBound), Ok((0, 0)));
+/// assert_eq!(prefix_sum_search(&offsets, 2, SearchMode::UpperBound), Ok((3, 5)));
+/// assert_eq!(prefix_sum_search(&offsets, 2, SearchMode::LowerBound), Ok((1, 2)));
+/// assert_eq!(prefix_sum_search(&offsets, 5, SearchMode::UpperBound), Err(5));
+/// assert_eq!(prefix_sum_search(&offsets, 5, SearchMode::LowerBound), Ok((3, 5)));
+/// ```
+pub fn prefix_sum_search<T: OffsetElement>(
+    offsets: &[T],
+    lookup: T::Sum,
+    mode: SearchMode,
+) -> Result<(usize, T::Sum), T::Sum> {
+    match mode {
+        SearchMode::UpperBound => prefix_sum_index_of(offsets, lookup),
+        // all the prefix sums are `>= 0`, so the first one matches.
+        SearchMode::LowerBound if lookup == T::Sum::default() => match offsets.first() {
+            Some(first) => Ok((0, first.widen())),
+            None => Err(lookup),
+        },
+        // for integers, `sum >= lookup` is the same as `sum > lookup - 1`,
+        // so all the backends can be used as-is.
+        SearchMode::LowerBound => prefix_sum_index_of(offsets, lookup - T::Sum::from(1)),
+    }
+}
+
+#[cfg(test)]
+fn lower_bound_reference(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    let mut sum = 0;
+    for (i, offset) in offsets.iter().enumerate() {
+        sum += *offset as usize;
+        if sum >= lookup {
+            return Ok((i, sum));
+        }
+    }
+    Err(sum)
+}
+
+#[test]
+fn test_search_modes() {
+    use crate::prefix_sum_fallback;
+
+    let offsets: [u8; 20] = [0, 0, 3, 0, 0, 0, 1, 0, 0, 255, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0];
+    for len in 0..=offsets.len() {
+        let offsets = &offsets[..len];
+        for lookup in 0..265 {
+            assert_eq!(
+                prefix_sum_search(offsets, lookup, SearchMode::UpperBound),
+                prefix_sum_fallback(offsets, lookup)
+            );
+            assert_eq!(
+                prefix_sum_search(offsets, lookup, SearchMode::LowerBound),
+                lower_bound_reference(offsets, lookup)
+            );
+            assert_eq!(
+                prefix_sum_search(
+                    &offsets.iter().map(|o| *o as u32).collect::<Vec<_>>(),
+                    lookup as u64,
+                    SearchMode::LowerBound
+                ),
+                lower_bound_reference(offsets, lookup)
+                    .map(|(idx, sum)| (idx, sum as u64))
+                    .map_err(|sum| sum as u64)
+            );
+        }
+    }
+}
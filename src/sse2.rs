@@ -0,0 +1,452 @@
+use core::arch::x86_64::*;
+
+use crate::scan::ScanOutput;
+
+/// Calculate the Prefix Sum Index using SSE2 intrinsics.
+///
+/// SSE2 is part of the `x86_64` baseline, so this needs neither a
+/// `target_feature` nor any runtime feature detection.
+///
+/// See [`crate level docs`](crate) for more information.
+pub fn prefix_sum_16(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    let mut start = 0;
+    let mut index = 0;
+
+    let mut chunks = offsets.chunks_exact(16);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees this is a `&[u8; 16]`
+        // we can avoid this in the future once `array_chunks` is stable.
+        let chunk = unsafe { &*(chunk as *const [u8] as *const [u8; 16]) };
+        match prefix_sum_16_inner(chunk, lookup - start) {
+            Ok((idx, sum)) => return Ok((index + idx, start + sum)),
+            Err(sum) => start += sum,
+        }
+        index += 16;
+    }
+    let remainder = chunks.remainder();
+    if remainder.is_empty() {
+        return Err(start);
+    }
+    // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 16.
+    let (mm, skip) = unsafe { load_remainder::<16>(offsets, remainder.len()) };
+    match prefix_sum_16_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip, start + sum)),
+        Err(sum) => Err(start + sum),
+    }
+}
+
+fn prefix_sum_16_inner(offsets: &[u8; 16], lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY: we do an unaligned load of exactly 16 bytes from a 16-byte array.
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    prefix_sum_16_simd(mm, lookup)
+}
+
+fn prefix_sum_16_simd(offsets: __m128i, lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY:
+    // - all the intrinsics are SSE2, which every `x86_64` CPU supports.
+    // - the prefix sum itself is bounded to `u8::MAX * 16`, which is `< i16::MAX`.
+    // - we check that lookup is `< i16::MAX` to avoid overflow.
+    unsafe {
+        // spread the 16xu8 out to 2 times 8xi16, by interleaving them with zeroes
+        let zero = _mm_setzero_si128();
+        let (lo, hi) = scan_16(
+            _mm_unpacklo_epi8(offsets, zero),
+            _mm_unpackhi_epi8(offsets, zero),
+        );
+
+        let total = _mm_extract_epi16::<7>(hi) as u16 as usize;
+        if lookup > i16::MAX as usize {
+            return Err(total);
+        }
+
+        // compare each i16 with our lookup
+        let lookup = _mm_set1_epi16(lookup as i16);
+        let cmp_lo = _mm_cmpgt_epi16(lo, lookup);
+        let cmp_hi = _mm_cmpgt_epi16(hi, lookup);
+
+        // narrow the 2 times 8*i16 masks into 16*i8, and compress those into one i32
+        let mask = _mm_movemask_epi8(_mm_packs_epi16(cmp_lo, cmp_hi));
+        // get the number of *trailing* zeros
+        // trailing, because we are dealing with little-endian bytes here
+        let idx = mask.trailing_zeros() as usize;
+        if idx > 15 {
+            Err(total)
+        } else {
+            Ok((idx, sum_first_bytes(offsets, idx + 1)))
+        }
+    }
+}
+
+/// Calculates the prefix sum of the 16xi16, split into two halves.
+#[inline(always)]
+unsafe fn scan_16(mut lo: __m128i, mut hi: __m128i) -> (__m128i, __m128i) {
+    // do the prefix sum, simplified like this, except we have 8 values:
+    //   [a,     b,         c,             d]
+    // + [0,     0, a        ,     b        ]
+    // = [a, b    , a + c    ,     b     + d]
+    // + [0, a    , b        , a     + c    ]
+    // = [a, a + b, a + b + c, a + b + c + d]
+    lo = _mm_add_epi16(lo, _mm_slli_si128::<2>(lo));
+    hi = _mm_add_epi16(hi, _mm_slli_si128::<2>(hi));
+    lo = _mm_add_epi16(lo, _mm_slli_si128::<4>(lo));
+    hi = _mm_add_epi16(hi, _mm_slli_si128::<4>(hi));
+    lo = _mm_add_epi16(lo, _mm_slli_si128::<8>(lo));
+    hi = _mm_add_epi16(hi, _mm_slli_si128::<8>(hi));
+
+    // broadcast the last 16-bit element of `lo`, and add it to all of `hi`
+    let carry = _mm_shufflehi_epi16::<0b11_11_11_11>(lo);
+    let carry = _mm_unpackhi_epi64(carry, carry);
+    (lo, _mm_add_epi16(hi, carry))
+}
+
+/// Writes the prefix sums of `offsets`, 16 at a time, to `out`.
+///
+/// Only whole chunks of 16 are processed, the sums start at `carry`, and
+/// wrap around on overflow. Returns the sum after the last processed chunk.
+pub fn prefix_sums_16<O: ScanOutput, const EXCLUSIVE: bool>(
+    offsets: &[u8],
+    out: &mut [O],
+    mut carry: u64,
+) -> u64 {
+    assert!(out.len() >= offsets.len());
+    let out = out.as_mut_ptr() as *mut u8;
+
+    for (i, chunk) in offsets.chunks_exact(16).enumerate() {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load, and we
+        // asserted that `out` has room for 16 integers.
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let mm = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            let (widened_lo, widened_hi) =
+                (_mm_unpacklo_epi8(mm, zero), _mm_unpackhi_epi8(mm, zero));
+            let (mut lo, mut hi) = scan_16(widened_lo, widened_hi);
+            let total = _mm_extract_epi16::<7>(hi) as u16 as u64;
+            if EXCLUSIVE {
+                lo = _mm_sub_epi16(lo, widened_lo);
+                hi = _mm_sub_epi16(hi, widened_hi);
+            }
+
+            let out = out.add(i * 16 * O::BYTES) as *mut __m128i;
+            match O::BYTES {
+                2 => {
+                    let carry = _mm_set1_epi16(carry as i16);
+                    _mm_storeu_si128(out, _mm_add_epi16(lo, carry));
+                    _mm_storeu_si128(out.add(1), _mm_add_epi16(hi, carry));
+                }
+                4 => {
+                    let carry = _mm_set1_epi32(carry as i32);
+                    let parts = [
+                        _mm_unpacklo_epi16(lo, zero),
+                        _mm_unpackhi_epi16(lo, zero),
+                        _mm_unpacklo_epi16(hi, zero),
+                        _mm_unpackhi_epi16(hi, zero),
+                    ];
+                    for (j, part) in parts.into_iter().enumerate() {
+                        _mm_storeu_si128(out.add(j), _mm_add_epi32(part, carry));
+                    }
+                }
+                _ => {
+                    let carry = _mm_set1_epi64x(carry as i64);
+                    let parts = [
+                        _mm_unpacklo_epi16(lo, zero),
+                        _mm_unpackhi_epi16(lo, zero),
+                        _mm_unpacklo_epi16(hi, zero),
+                        _mm_unpackhi_epi16(hi, zero),
+                    ];
+                    for (j, part) in parts.into_iter().enumerate() {
+                        let lo = _mm_unpacklo_epi32(part, zero);
+                        let hi = _mm_unpackhi_epi32(part, zero);
+                        _mm_storeu_si128(out.add(j * 2), _mm_add_epi64(lo, carry));
+                        _mm_storeu_si128(out.add(j * 2 + 1), _mm_add_epi64(hi, carry));
+                    }
+                }
+            }
+            carry = carry.wrapping_add(total);
+        }
+    }
+    carry
+}
+
+/// Calculate the Prefix Sum Index of `u16` offsets using SSE2 intrinsics.
+///
+/// See [`prefix_sum_index_u16`](crate::prefix_sum_index_u16) for more information.
+pub fn prefix_sum_u16_8(offsets: &[u16], lookup: usize) -> Result<(usize, usize), usize> {
+    let mut start = 0;
+    let mut index = 0;
+
+    let mut chunks = offsets.chunks_exact(8);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load.
+        let mm = unsafe { _mm_loadu_si128(chunk.as_ptr() as *const __m128i) };
+        match prefix_sum_u16_8_simd(mm, lookup - start) {
+            Ok((idx, sum)) => return Ok((index + idx, start + sum)),
+            Err(sum) => start += sum,
+        }
+        index += 8;
+    }
+    let remainder = chunks.remainder();
+    if remainder.is_empty() {
+        return Err(start);
+    }
+    // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 16 bytes.
+    let (mm, skip) = unsafe { load_remainder::<16>(as_bytes(offsets), remainder.len() * 2) };
+    match prefix_sum_u16_8_simd(mm, lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx - skip / 2, start + sum)),
+        Err(sum) => Err(start + sum),
+    }
+}
+
+fn prefix_sum_u16_8_simd(offsets: __m128i, lookup: usize) -> Result<(usize, usize), usize> {
+    // SAFETY:
+    // - all the intrinsics are SSE2, which every `x86_64` CPU supports.
+    // - the prefix sum itself is bounded to `u16::MAX * 8`, which is `< i32::MAX`.
+    // - we check that lookup is `< i32::MAX` to avoid overflow.
+    unsafe {
+        // spread the 8xu16 out to 2 times 4xi32, by interleaving them with zeroes
+        let zero = _mm_setzero_si128();
+        let mut lo = _mm_unpacklo_epi16(offsets, zero);
+        let mut hi = _mm_unpackhi_epi16(offsets, zero);
+
+        // the same prefix sum as for the `u8`s, just with 32-bit elements
+        lo = _mm_add_epi32(lo, _mm_slli_si128::<4>(lo));
+        hi = _mm_add_epi32(hi, _mm_slli_si128::<4>(hi));
+        lo = _mm_add_epi32(lo, _mm_slli_si128::<8>(lo));
+        hi = _mm_add_epi32(hi, _mm_slli_si128::<8>(hi));
+
+        // broadcast the last 32-bit element of `lo`, and add it to all of `hi`
+        hi = _mm_add_epi32(hi, _mm_shuffle_epi32::<0b11_11_11_11>(lo));
+
+        let total = _mm_cvtsi128_si32(_mm_shuffle_epi32::<0b11_11_11_11>(hi)) as u32 as usize;
+        if lookup > i32::MAX as usize {
+            return Err(total);
+        }
+
+        // compare each i32 with our lookup
+        let lookup = _mm_set1_epi32(lookup as i32);
+        let cmp_lo = _mm_cmpgt_epi32(lo, lookup);
+        let cmp_hi = _mm_cmpgt_epi32(hi, lookup);
+
+        // narrow the 2 times 4*i32 masks into 8*i16, and compress those into one i32
+        let mask = _mm_movemask_epi8(_mm_packs_epi32(cmp_lo, cmp_hi));
+        let idx = mask.trailing_zeros() as usize / 2;
+        if idx > 7 {
+            return Err(total);
+        }
+
+        // keep only the matching element, and move it to the front
+        let idx_mm = _mm_set1_epi32(idx as i32);
+        let lo = _mm_and_si128(lo, _mm_cmpeq_epi32(_mm_setr_epi32(0, 1, 2, 3), idx_mm));
+        let hi = _mm_and_si128(hi, _mm_cmpeq_epi32(_mm_setr_epi32(4, 5, 6, 7), idx_mm));
+        let sum = _mm_or_si128(lo, hi);
+        let sum = _mm_or_si128(sum, _mm_shuffle_epi32::<0b01_00_11_10>(sum));
+        let sum = _mm_or_si128(sum, _mm_shuffle_epi32::<0b10_11_00_01>(sum));
+        Ok((idx, _mm_cvtsi128_si32(sum) as u32 as usize))
+    }
+}
+
+/// Sums up all the `offsets` using SSE2 intrinsics.
+pub fn sum(offsets: &[u8]) -> usize {
+    // SAFETY: SSE2 is supported by every `x86_64` CPU.
+    unsafe { sum_inline(offsets) }
+}
+
+/// Sums up all the `offsets` with `sad`, 16 bytes at a time.
+#[inline(always)]
+pub(crate) unsafe fn sum_inline(offsets: &[u8]) -> usize {
+    let zero = _mm_setzero_si128();
+    // the `sad` results are u64s, which can not overflow
+    let mut sums = zero;
+
+    let mut chunks = offsets.chunks_exact(16);
+    for chunk in &mut chunks {
+        // SAFETY: `chunks_exact` guarantees we have 16 bytes to load.
+        let mm = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+        sums = _mm_add_epi64(sums, _mm_sad_epu8(mm, zero));
+    }
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        // SAFETY: `remainder` is the non-empty end of `offsets`, and shorter than 16.
+        let (mm, _skip) = load_remainder::<16>(offsets, remainder.len());
+        sums = _mm_add_epi64(sums, _mm_sad_epu8(mm, zero));
+    }
+
+    let sums = _mm_add_epi64(sums, _mm_unpackhi_epi64(sums, sums));
+    _mm_cvtsi128_si64(sums) as usize
+}
+
+// The helpers below are shared with the other x86 backends. They are
+// `inline(always)`, so they get compiled with the target features of the
+// function they are inlined into.
+
+/// Returns `[0, 1, 2, ..., 15]`, the index of each byte.
+#[inline(always)]
+unsafe fn byte_indices() -> __m128i {
+    _mm_setr_epi8(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15)
+}
+
+/// Sums up the first `n <= 16` bytes of `bytes`.
+///
+/// This is used to get the prefix sum at a runtime index, without having to
+/// store the scanned lanes to the stack.
+#[inline(always)]
+pub(crate) unsafe fn sum_first_bytes(bytes: __m128i, n: usize) -> usize {
+    let keep = _mm_cmplt_epi8(byte_indices(), _mm_set1_epi8(n as i8));
+    // `sad` sums up the absolute difference to zero of each 8 bytes into a u64
+    let sums = _mm_sad_epu8(_mm_and_si128(bytes, keep), _mm_setzero_si128());
+    let sums = _mm_add_epi64(sums, _mm_unpackhi_epi64(sums, sums));
+    _mm_cvtsi128_si32(sums) as usize
+}
+
+/// Sets the first `n <= 16` bytes of `bytes` to zero.
+#[inline(always)]
+unsafe fn zero_first_bytes(bytes: __m128i, n: usize) -> __m128i {
+    let clear = _mm_cmplt_epi8(byte_indices(), _mm_set1_epi8(n as i8));
+    _mm_andnot_si128(clear, bytes)
+}
+
+/// Loads the last `len` bytes of `offsets` into the low `N` bytes of a register.
+///
+/// If `offsets` is long enough, this does an overlapping load of its last `N`
+/// bytes, and zeroes out the leading bytes that do not belong to the
+/// remainder. As zero offsets do not change the prefix sum, the result of
+/// a lookup in those is the same, except for its index being shifted.
+///
+/// Returns the register and the number of bytes the remainder is shifted by.
+///
+/// # Safety
+///
+/// `len` has to be in `1..N` and not greater than `offsets.len()`, and `N`
+/// either `8` or `16`.
+#[inline(always)]
+pub(crate) unsafe fn load_remainder<const N: usize>(
+    offsets: &[u8],
+    len: usize,
+) -> (__m128i, usize) {
+    if offsets.len() >= N {
+        let skip = N - len;
+        let ptr = offsets.as_ptr().add(offsets.len() - N) as *const __m128i;
+        let mm = if N == 16 {
+            _mm_loadu_si128(ptr)
+        } else {
+            _mm_loadl_epi64(ptr)
+        };
+        (zero_first_bytes(mm, skip), skip)
+    } else {
+        // the slice is too short for an overlapping load, and we must not
+        // read out of bounds, so copy it over instead.
+        let mut buf = [0u8; 16];
+        buf[..len].copy_from_slice(&offsets[offsets.len() - len..]);
+        (_mm_loadu_si128(buf.as_ptr() as *const __m128i), 0)
+    }
+}
+
+/// Primitive integers, which can be safely reinterpreted as bytes.
+pub(crate) trait Primitive: Copy {}
+impl Primitive for u16 {}
+impl Primitive for u32 {}
+
+/// Reinterprets the integers as native-endian bytes.
+#[inline(always)]
+pub(crate) fn as_bytes<T: Primitive>(offsets: &[T]) -> &[u8] {
+    // SAFETY: primitive integers have no padding, `u8` has no alignment
+    // requirements, and the length covers exactly the same memory.
+    unsafe {
+        core::slice::from_raw_parts(
+            offsets.as_ptr() as *const u8,
+            core::mem::size_of_val(offsets),
+        )
+    }
+}
+
+#[cfg(test)]
+use crate::{fallback::prefix_sum_scalar, prefix_sum_fallback};
+
+#[test]
+fn test_sse2_16() {
+    let offsets = [
+        0, //  0
+        1, //  1
+        0, //  1
+        4, //  5
+        8, // 13
+        1, // 14
+        2, // 16
+        9, // 25
+        8, // 33
+        1, // 34
+        4, // 38
+        1, // 39
+        3, // 42
+        7, // 49
+        1, // 50
+        6, // 56
+    ];
+    for lookup in [0, 1, 7, 16, 25, 33, 52, 60] {
+        assert_eq!(
+            prefix_sum_16_inner(&offsets, lookup),
+            prefix_sum_fallback(&offsets, lookup)
+        );
+    }
+
+    let offsets = [255; 16];
+    assert_eq!(prefix_sum_16_inner(&offsets, 1 << 34), Err(255 * 16));
+}
+
+#[test]
+fn test_remainder() {
+    let offsets: Vec<u8> = (1..=20).collect();
+    for len in 1..16 {
+        let (mm, skip) = unsafe { load_remainder::<16>(&offsets, len) };
+        assert_eq!(skip, 16 - len);
+        let tail = &offsets[20 - len..];
+        assert_eq!(
+            unsafe { sum_first_bytes(mm, 16) },
+            tail.iter().map(|o| *o as usize).sum::<usize>()
+        );
+
+        let (mm, skip) = unsafe { load_remainder::<16>(tail, len) };
+        assert_eq!(skip, 0);
+        assert_eq!(unsafe { sum_first_bytes(mm, len) }, unsafe {
+            sum_first_bytes(mm, 16)
+        });
+    }
+}
+
+#[test]
+fn test_sse2_u16() {
+    let offsets: [u16; 8] = [
+        0,      //      0
+        1,      //      1
+        300,    //    301
+        4,      //    305
+        65_535, // 65_840
+        1,      // 65_841
+        2,      // 65_843
+        9,      // 65_852
+    ];
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    for lookup in [0, 1, 300, 301, 305, 65_839, 65_840, 65_851, 65_852, 70_000] {
+        assert_eq!(
+            prefix_sum_u16_8_simd(mm, lookup),
+            prefix_sum_scalar(&offsets, lookup)
+        );
+    }
+
+    let offsets = [u16::MAX; 8];
+    let mm = unsafe { _mm_loadu_si128(offsets.as_ptr() as *const __m128i) };
+    assert_eq!(
+        prefix_sum_u16_8_simd(mm, 1 << 34),
+        Err(u16::MAX as usize * 8)
+    );
+}
+
+#[test]
+fn test_sum() {
+    let offsets: Vec<u8> = (0..=255).chain(0..=255).collect();
+    for from in [0, 1, 15, 16, 17, 100] {
+        for to in [from, from + 1, from + 15, from + 16, from + 33, 512] {
+            let slice = &offsets[from..to];
+            assert_eq!(sum(slice), slice.iter().map(|o| *o as usize).sum());
+        }
+    }
+}
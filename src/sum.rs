@@ -0,0 +1,88 @@
+use crate::{Backend, OffsetElement};
+
+/// Calculate the prefix sum *before* the entry at `index`.
+///
+/// This is the inverse of [`prefix_sum_index`](crate::prefix_sum_index), and
+/// returns the position where the entry at `index` starts. An `index` equal
+/// to the length of `offsets` returns the sum of all the `offsets`.
+///
+/// # Panics
+///
+/// Panics if `index` is greater than the length of `offsets`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::prefix_sum_at;
+///
+/// let offsets: [u8; 4] = [12, 0, 30, 7];
+///
+/// assert_eq!(prefix_sum_at(&offsets, 0), 0);
+/// assert_eq!(prefix_sum_at(&offsets, 2), 12);
+/// assert_eq!(prefix_sum_at(&offsets, 4), 49);
+/// ```
+pub fn prefix_sum_at<T: OffsetElement>(offsets: &[T], index: usize) -> T::Sum {
+    range_sum(offsets, 0, index)
+}
+
+/// Calculate the sum of the entries in `from..to`.
+///
+/// `u8` offsets are summed up using SIMD horizontal sums where available.
+///
+/// # Panics
+///
+/// Panics if `from` is greater than `to`, or `to` is greater than the length
+/// of `offsets`.
+///
+/// # Examples
+///
+/// ```
+/// use psy::range_sum;
+///
+/// let offsets: [u8; 4] = [12, 0, 30, 7];
+///
+/// assert_eq!(range_sum(&offsets, 1, 3), 30);
+/// assert_eq!(range_sum(&offsets, 2, 2), 0);
+/// ```
+pub fn range_sum<T: OffsetElement>(offsets: &[T], from: usize, to: usize) -> T::Sum {
+    // SAFETY: `detect` only returns backends supported by the current CPU.
+    unsafe { T::sum_unchecked(Backend::detect(), &offsets[from..to]) }
+}
+
+#[test]
+fn test_sums() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let offsets: Vec<u8> = (0..1_000).map(|_| rng.gen()).collect();
+    for _ in 0..200 {
+        let to = rng.gen_range(0..=offsets.len());
+        let from = rng.gen_range(0..=to);
+        let expected = offsets[from..to].iter().map(|o| *o as usize).sum::<usize>();
+
+        assert_eq!(range_sum(&offsets, from, to), expected);
+        for backend in Backend::available() {
+            let sum = unsafe { u8::sum_unchecked(backend, &offsets[from..to]) };
+            assert_eq!(sum, expected, "{}", backend);
+        }
+
+        let offsets_u32: Vec<u32> = offsets.iter().map(|o| *o as u32 * 1_000_000).collect();
+        assert_eq!(
+            range_sum(&offsets_u32, from, to),
+            expected as u64 * 1_000_000
+        );
+    }
+
+    for index in 0..=offsets.len() {
+        match crate::prefix_sum_index(&offsets, prefix_sum_at(&offsets, index)) {
+            // the entry found at the start of `index`, is `index` itself,
+            // unless it is empty.
+            Ok((found, _)) => assert!(found >= index),
+            Err(_) => assert_eq!(
+                prefix_sum_at(&offsets, index),
+                prefix_sum_at(&offsets, 1_000)
+            ),
+        }
+    }
+}
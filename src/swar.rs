@@ -0,0 +1,161 @@
+//! SIMD within a register (SWAR), using plain `u64` arithmetic.
+
+/// Low bit of each 16-bit lane.
+const LANES_LO: u64 = 0x0001_0001_0001_0001;
+/// High bit of each 16-bit lane.
+const LANES_HI: u64 = 0x8000_8000_8000_8000;
+/// Low byte of each 16-bit lane.
+const LANES_BYTE: u64 = 0x00FF_00FF_00FF_00FF;
+
+/// Calculate the Prefix Sum Index using SWAR (SIMD within a register).
+///
+/// This processes 8 offsets at a time using plain `u64` arithmetic, so it
+/// works on every target.
+///
+/// See [`crate level docs`](crate) for more information.
+pub fn prefix_sum_8(offsets: &[u8], lookup: usize) -> Result<(usize, usize), usize> {
+    let mut start = 0;
+    let mut index = 0;
+
+    let mut chunks = offsets.chunks_exact(8);
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap());
+        match prefix_sum_8_inner(word, lookup - start) {
+            Ok((idx, sum)) => return Ok((index + idx, start + sum)),
+            Err(sum) => start += sum,
+        }
+        index += 8;
+    }
+    let remainder = chunks.remainder();
+    let mut buf = [0u8; 8];
+    {
+        let (prefix, _) = buf.split_at_mut(remainder.len());
+        prefix.copy_from_slice(remainder);
+    }
+    match prefix_sum_8_inner(u64::from_le_bytes(buf), lookup - start) {
+        Ok((idx, sum)) => Ok((index + idx, start + sum)),
+        Err(sum) => Err(start + sum),
+    }
+}
+
+/// Works on 8 offsets packed into a little-endian `u64`.
+fn prefix_sum_8_inner(offsets: u64, lookup: usize) -> Result<(usize, usize), usize> {
+    // spread the 8xu8 out to 2 times 4xu16, the even bytes end up in `lo`,
+    // the odd bytes in `hi`.
+    let lo = offsets & LANES_BYTE;
+    let hi = (offsets >> 8) & LANES_BYTE;
+
+    // the even and odd lanes are interleaved, so their pairwise sums give us
+    // the sums of each *pair* of offsets:
+    //   [a + b, c + d, e + f, g + h]
+    let pairs = lo + hi;
+    // multiplying with `LANES_LO` is the same as adding the value shifted to
+    // every lane, which gives us the prefix sum of the pairs:
+    //   [a + b, a + b + c + d, ...]
+    // the sums are bounded to `u8::MAX * 8`, so none of the lanes overflow.
+    let odd = pairs.wrapping_mul(LANES_LO);
+    // the prefix sums at the even indices are the ones of the odd indices,
+    // minus the odd offset itself:
+    //   [a, a + b + c, ...]
+    let even = odd - hi;
+
+    let total = (odd >> 48) as usize;
+    if lookup > i16::MAX as usize {
+        return Err(total);
+    }
+
+    // both the sums and the lookup are `<= i16::MAX`, so adding
+    // `i16::MAX - lookup` sets the high bit of exactly those lanes that are
+    // greater than `lookup`, without carrying into the next lane.
+    let bias = (i16::MAX as u64 - lookup as u64) * LANES_LO;
+    let even_mask = (even + bias) & LANES_HI;
+    let odd_mask = (odd + bias) & LANES_HI;
+
+    // interleave the masks again, so each bit corresponds to one offset
+    let mask = (even_mask >> 8) | odd_mask;
+    // the first matching offset is the lowest set bit, we have one bit at the
+    // top of each byte
+    let idx = mask.trailing_zeros() as usize / 8;
+    if idx > 7 {
+        return Err(total);
+    }
+    let lanes = if idx & 1 == 0 { even } else { odd };
+    let sum = (lanes >> (idx / 2 * 16)) & 0xFFFF;
+    Ok((idx, sum as usize))
+}
+
+/// Sums up all the `offsets` using SWAR (SIMD within a register).
+pub fn sum(offsets: &[u8]) -> usize {
+    let mut total = 0;
+
+    let mut chunks = offsets.chunks_exact(8);
+    // each word adds at most `2 * u8::MAX` to each of the 16-bit lanes, so we
+    // can add up 128 words before having to empty the lanes.
+    while chunks.len() > 0 {
+        let mut lanes = 0;
+        for chunk in (&mut chunks).take(128) {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            lanes += (word & LANES_BYTE) + ((word >> 8) & LANES_BYTE);
+        }
+        // the sum of all the lanes might not fit into 16 bits, so widen them
+        // to 32 bits before adding them up
+        let pairs = (lanes & 0x0000_FFFF_0000_FFFF) + ((lanes >> 16) & 0x0000_FFFF_0000_FFFF);
+        total += ((pairs & 0xFFFF_FFFF) + (pairs >> 32)) as usize;
+    }
+
+    total
+        + chunks
+            .remainder()
+            .iter()
+            .map(|offset| *offset as usize)
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+use crate::prefix_sum_fallback;
+
+#[test]
+fn test_swar_8() {
+    let offsets = [
+        0, //  0
+        1, //  1
+        0, //  1
+        4, //  5
+        8, // 13
+        1, // 14
+        2, // 16
+        9, // 25
+    ];
+    let word = u64::from_le_bytes(offsets);
+    for lookup in [0, 1, 7, 13, 16, 24, 25, 30] {
+        assert_eq!(
+            prefix_sum_8_inner(word, lookup),
+            prefix_sum_fallback(&offsets, lookup)
+        );
+    }
+
+    let offsets = [255; 8];
+    let word = u64::from_le_bytes(offsets);
+    for lookup in [0, 254, 255, 1000, 2039, 2040] {
+        assert_eq!(
+            prefix_sum_8_inner(word, lookup),
+            prefix_sum_fallback(&offsets, lookup)
+        );
+    }
+    assert_eq!(prefix_sum_8_inner(word, usize::MAX), Err(255 * 8));
+}
+
+#[test]
+fn test_sum() {
+    assert_eq!(sum(&[]), 0);
+    assert_eq!(sum(&[255; 8 * 128]), 255 * 8 * 128);
+    assert_eq!(sum(&[255; 8 * 129 + 3]), 255 * (8 * 129 + 3));
+
+    let offsets: Vec<u8> = (0..=255).chain(0..=255).collect();
+    for from in [0, 1, 7, 8, 9, 100] {
+        for to in [from, from + 1, from + 7, from + 8, from + 17, 512] {
+            let slice = &offsets[from..to];
+            assert_eq!(sum(slice), slice.iter().map(|o| *o as usize).sum());
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use std::fmt;
+
+use crate::prefix_sum_index;
+
+/// The number of entries between two checkpoints, a power of two.
+const CHECKPOINT_INTERVAL: usize = 128;
+
+/// The position and prefix sum at the start of every
+/// [`CHECKPOINT_INTERVAL`] entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Checkpoint {
+    /// The byte position of the entry.
+    position: usize,
+    /// The prefix sum before the entry.
+    start: u64,
+}
+
+/// The error returned when decoding invalid LEB128 varints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidVarint {
+    /// The byte position of the invalid varint.
+    pub position: usize,
+}
+
+impl fmt::Display for InvalidVarint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid LEB128 varint at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for InvalidVarint {}
+
+/// A stream of offsets encoded as unsigned LEB128 varints.
+///
+/// Next to the encoded bytes, this stores a checkpoint with the byte position
+/// and prefix sum every 128 entries. Lookups binary-search the checkpoints,
+/// and then only decode a single block. Within that block, runs of bytes
+/// below `0x80` are offsets that fit into a single byte, and are searched
+/// with [`prefix_sum_index`] directly, without decoding them one by one.
+///
+/// # Examples
+///
+/// ```
+/// use psy::VarintOffsets;
+///
+/// let offsets: VarintOffsets = [12, 0, 300, 30].into_iter().collect();
+///
+/// assert_eq!(offsets.bytes(), &[12, 0, 0xAC, 0x02, 30]);
+/// assert_eq!(offsets.get(2), Some(300));
+/// assert_eq!(offsets.prefix_sum_index(11), Ok((0, 12)));
+/// assert_eq!(offsets.prefix_sum_index(12), Ok((2, 312)));
+/// assert_eq!(offsets.prefix_sum_index(312), Ok((3, 342)));
+/// assert_eq!(offsets.prefix_sum_index(342), Err(342));
+///
+/// let decoded = VarintOffsets::from_leb128(offsets.bytes().to_vec()).unwrap();
+/// assert_eq!(decoded.prefix_sum_index(12), Ok((2, 312)));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VarintOffsets {
+    bytes: Vec<u8>,
+    len: usize,
+    total: u64,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl VarintOffsets {
+    /// Creates an empty stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a stream from already encoded LEB128 `bytes`.
+    ///
+    /// Returns an error if `bytes` ends in the middle of a varint, a varint
+    /// does not fit into a `u64`, or the sum of all the offsets does not fit
+    /// into a `u64`.
+    pub fn from_leb128(bytes: Vec<u8>) -> Result<Self, InvalidVarint> {
+        let mut offsets = Self::new();
+        let mut position = 0;
+        while position < bytes.len() {
+            let invalid = InvalidVarint { position };
+            let (value, len) = decode(&bytes[position..]).ok_or(invalid)?;
+            offsets.checkpoint(position);
+            offsets.total = offsets.total.checked_add(value).ok_or(invalid)?;
+            offsets.len += 1;
+            position += len;
+        }
+        offsets.bytes = bytes;
+        Ok(offsets)
+    }
+
+    /// The encoded LEB128 bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Consumes the stream, returning the encoded LEB128 bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The number of offsets.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no offsets.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The sum of all the offsets.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Appends an offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum of all the offsets does not fit into a `u64`.
+    pub fn push(&mut self, mut offset: u64) {
+        self.checkpoint(self.bytes.len());
+        self.total = self
+            .total
+            .checked_add(offset)
+            .expect("the sum of the offsets has to fit into a `u64`");
+        self.len += 1;
+
+        while offset >= 0x80 {
+            self.bytes.push(offset as u8 | 0x80);
+            offset >>= 7;
+        }
+        self.bytes.push(offset as u8);
+    }
+
+    /// Returns the offset at `index`, or `None` if it is out of bounds.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.len {
+            return None;
+        }
+        let (position, _) = self.skip_to(index);
+        decode(&self.bytes[position..]).map(|(value, _)| value)
+    }
+
+    /// Calculate the prefix sum *before* the entry at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than [`len`](Self::len).
+    pub fn prefix_sum_at(&self, index: usize) -> u64 {
+        assert!(index <= self.len);
+        let (_, sum) = self.skip_to(index);
+        sum
+    }
+
+    /// Calculate the Prefix Sum Index.
+    ///
+    /// This returns the same results as
+    /// [`prefix_sum_index_of`](crate::prefix_sum_index_of) would for the
+    /// decoded offsets.
+    pub fn prefix_sum_index(&self, lookup: u64) -> Result<(usize, u64), u64> {
+        if lookup >= self.total {
+            return Err(self.total);
+        }
+
+        // the last checkpoint that starts at or before `lookup`, the first
+        // one always starts at `0`.
+        let block = self.checkpoints.partition_point(|c| c.start <= lookup) - 1;
+        let Checkpoint {
+            mut position,
+            start: mut sum,
+        } = self.checkpoints[block];
+        let mut index = block * CHECKPOINT_INTERVAL;
+        // the entry we are looking for is within this block
+        let end = match self.checkpoints.get(block + 1) {
+            Some(next) => next.position,
+            None => self.bytes.len(),
+        };
+
+        loop {
+            let bytes = &self.bytes[position..end];
+            let run = bytes.iter().position(|b| *b >= 0x80).unwrap_or(bytes.len());
+            let remaining = usize::try_from(lookup - sum).unwrap_or(usize::MAX);
+            match prefix_sum_index(&bytes[..run], remaining) {
+                Ok((idx, run_sum)) => return Ok((index + idx, sum + run_sum as u64)),
+                Err(run_sum) => {
+                    sum += run_sum as u64;
+                    index += run;
+                    position += run;
+                }
+            }
+
+            // validated when constructing the stream
+            let (value, len) = decode(&self.bytes[position..]).unwrap();
+            sum += value;
+            if sum > lookup {
+                return Ok((index, sum));
+            }
+            index += 1;
+            position += len;
+        }
+    }
+
+    /// Records a checkpoint if the next entry starts a new block.
+    fn checkpoint(&mut self, position: usize) {
+        if self.len & (CHECKPOINT_INTERVAL - 1) == 0 {
+            self.checkpoints.push(Checkpoint {
+                position,
+                start: self.total,
+            });
+        }
+    }
+
+    /// Returns the byte position of, and the prefix sum before the entry at
+    /// `index`.
+    fn skip_to(&self, index: usize) -> (usize, u64) {
+        let block = index / CHECKPOINT_INTERVAL;
+        let Some(checkpoint) = self.checkpoints.get(block) else {
+            // `index` is the end of a stream with a full last block
+            return (self.bytes.len(), self.total);
+        };
+
+        let Checkpoint {
+            mut position,
+            start: mut sum,
+        } = *checkpoint;
+        for _ in block * CHECKPOINT_INTERVAL..index {
+            let (value, len) = decode(&self.bytes[position..]).unwrap();
+            sum += value;
+            position += len;
+        }
+        (position, sum)
+    }
+}
+
+impl Extend<u64> for VarintOffsets {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|offset| self.push(offset));
+    }
+}
+
+impl FromIterator<u64> for VarintOffsets {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut offsets = Self::new();
+        offsets.extend(iter);
+        offsets
+    }
+}
+
+/// Decodes the varint at the start of `bytes`, returning its value and
+/// length in bytes.
+fn decode(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        let bits = (*byte & 0x7F) as u64;
+        // the 10th byte only has a single bit left
+        if i == 9 && bits > 1 {
+            return None;
+        }
+        value |= bits << (i * 7);
+        if *byte < 0x80 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(&[]), None);
+    assert_eq!(decode(&[0]), Some((0, 1)));
+    assert_eq!(decode(&[0x7F, 0xFF]), Some((127, 1)));
+    assert_eq!(decode(&[0xAC, 0x02]), Some((300, 2)));
+    assert_eq!(decode(&[0x80, 0x00]), Some((0, 2)));
+    assert_eq!(decode(&[0x80]), None);
+
+    let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    assert_eq!(decode(&max), Some((u64::MAX, 10)));
+    let mut overflow = max;
+    overflow[9] = 0x02;
+    assert_eq!(decode(&overflow), None);
+    let mut too_long = max;
+    too_long[9] = 0x81;
+    assert_eq!(decode(&too_long), None);
+}
+
+#[test]
+fn test_varint() {
+    use rand::prelude::*;
+    use rand::rngs::SmallRng;
+
+    let offsets = VarintOffsets::new();
+    assert_eq!(offsets.prefix_sum_index(0), Err(0));
+    assert_eq!(offsets.prefix_sum_at(0), 0);
+    assert_eq!(offsets.get(0), None);
+
+    assert_eq!(
+        VarintOffsets::from_leb128(vec![1, 2, 0x80]).unwrap_err(),
+        InvalidVarint { position: 2 }
+    );
+    let max = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+    assert_eq!(
+        VarintOffsets::from_leb128([&[1][..], &max].concat()).unwrap_err(),
+        InvalidVarint { position: 1 }
+    );
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    for len in [1, 2, 127, 128, 129, 1_000] {
+        for large_ratio in [0.0, 0.1, 1.0] {
+            let values: Vec<u64> = (0..len)
+                .map(|_| match rng.gen_bool(large_ratio) {
+                    true => {
+                        let bits = rng.gen_range(7..40);
+                        rng.gen_range(0..1 << bits)
+                    }
+                    false => rng.gen_range(0..8),
+                })
+                .collect();
+            let offsets: VarintOffsets = values.iter().copied().collect();
+            let decoded = VarintOffsets::from_leb128(offsets.bytes().to_vec()).unwrap();
+            assert_eq!(decoded.checkpoints, offsets.checkpoints);
+            assert_eq!(offsets.len(), len);
+
+            let total = values.iter().sum::<u64>();
+            assert_eq!(offsets.total(), total);
+
+            for (index, value) in values.iter().enumerate() {
+                assert_eq!(offsets.get(index), Some(*value));
+            }
+            let mut lookups: Vec<u64> = (0..300).map(|_| rng.gen_range(0..total + 2)).collect();
+            lookups.extend([0, total.saturating_sub(1), total, u64::MAX]);
+            for lookup in lookups {
+                assert_eq!(
+                    offsets.prefix_sum_index(lookup),
+                    crate::prefix_sum_index_of(&values, lookup)
+                );
+            }
+            for index in 0..=len {
+                let expected = values[..index].iter().sum::<u64>();
+                assert_eq!(offsets.prefix_sum_at(index), expected);
+            }
+        }
+    }
+}